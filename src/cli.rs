@@ -1,10 +1,16 @@
-use std::{env, path::PathBuf, sync::LazyLock};
+use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use crate::daemon::{
-    state::{Horizontal, Vertical},
-    unit::Unit,
+use crate::{
+    daemon::{
+        fullscreen::FullscreenAction,
+        scratchpad::ScratchpadAction,
+        slot::parse_slot_name,
+        state::{Horizontal, Layout, Vertical},
+        unit::{AbsoluteUnit, Aspect, RelativeUnit, Unit},
+    },
+    log::Level,
 };
 
 /// Automatically position and resize a floating window in Sway.
@@ -18,22 +24,219 @@ use crate::daemon::{
 /// Events do not need included every possible property allowed, only the ones that need to be
 /// changed.
 ///
-/// `width` and `height` can both be provided as a percentage of the available area, or as a pixel
-/// value. When both `width` and `height` is provided, the window will be resized to exactly that
+/// `width` and `height` can both be provided as a percentage of the available area, as a pixel
+/// value, as a physical length (`mm`, `cm` or `in`), or as an expression like `calc(50% - 20px)`,
+/// `min(40%, 800px)`, `max(...)` or `clamp(...)`. Pixel values are logical pixels, unless given as
+/// device pixels (ex: `600dpx`). When both `width` and `height` is provided, the window will be resized to exactly that
 /// size. When only one dimension is provided, the other will be automatically calculated to
 /// maintain the aspect ratio of the window.
 #[derive(Debug, Parser)]
-#[command(version, about)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub placement: PlacementArgs,
+
+    #[command(flatten)]
+    pub size: SizeArgs,
+
+    #[command(flatten)]
+    pub fullscreen: FullscreenArgs,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+
+    /// The path to use for the socket to listen on
+    ///
+    /// By default, this is unique to the sway session (based on `SWAYSOCK`). Outside of a sway
+    /// session, clients use the only running daemon if there is exactly one.
+    #[arg(short, long, global = true)]
+    pub socket: Option<PathBuf>,
+
+    /// A file to keep the daemon's state in, so `--oneshot` can start from it
+    ///
+    /// The daemon writes its state there when it exits, and `--oneshot` reads it instead of
+    /// starting from the defaults.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub state_file: Option<PathBuf>,
+
+    /// Run as a daemon, and wait for events via IPC
+    #[arg(short, long, conflicts_with_all = ["dry_run", "oneshot"])]
+    pub daemon: bool,
+
+    #[command(flatten)]
+    pub daemon_options: DaemonOptions,
+
+    #[command(flatten)]
+    pub target: TargetArgs,
+
+    /// Hide the window in the scratchpad, or show it again in its previous place
+    #[arg(long, value_enum)]
+    pub scratchpad: Option<ScratchpadAction>,
+
+    /// Make the focused tiled window floating, then place it
+    #[arg(long, conflicts_with = "unfloat")]
+    pub float: bool,
+
+    /// Make the focused window floating and sticky, then place it
+    #[arg(long, conflicts_with = "unfloat")]
+    pub sticky: bool,
+
+    /// Return the window to where it was tiled before `--float` or `--sticky`
+    #[arg(long)]
+    pub unfloat: bool,
+
+    /// Show the status of the running daemon, including its connection to sway
+    #[arg(long)]
+    pub status: bool,
+
+    /// List every running daemon, with its sway session, PID and uptime
+    #[arg(long)]
+    pub list_daemons: bool,
+
+    /// Forward each line of stdin to the daemon over one connection, and print the replies
+    ///
+    /// Lines can be events as JSON, or the same arguments as the client (ex: `top left`).
+    #[arg(long, conflicts_with_all = ["dry_run", "oneshot"])]
+    pub stdin: bool,
+
+    /// Read one update per line of stdin, and have the daemon apply all of them at once
+    ///
+    /// Lines take the same arguments as the client (ex: `--slot pip top left`). If any of the
+    /// updates fails, the daemon keeps its previous state and puts the windows back accordingly.
+    #[arg(long, conflicts_with_all = ["stdin", "dry_run", "oneshot"])]
+    pub batch: bool,
+
+    /// Instruct the running daemon to shutdown
+    #[arg(long)]
+    pub shutdown: bool,
+}
+
+/// The focused commands, which are the same as the flags of the shorthand form
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the daemon, starting from the given state
+    Daemon(DaemonArgs),
+    /// Move the window to one of the 9 spots on the workspace, and optionally resize it
+    Move(MoveArgs),
+    /// Resize the window, keeping it in its spot
+    Resize(ResizeArgs),
+    /// Make the focused tiled window floating, then place it
+    Float(FloatArgs),
+    /// Return the window to where it was tiled before it was floated
+    Unfloat,
+    /// Hide the window in the scratchpad, or show it again in its previous place
+    Scratchpad {
+        #[arg(value_enum)]
+        action: ScratchpadAction,
+    },
+    /// Show the status of the running daemon, including its connection to sway
+    Status,
+    /// List every running daemon, with its sway session, PID and uptime
+    ListDaemons,
+    /// Read one update per line of stdin, and have the daemon apply all of them at once
+    Batch,
+    /// Forward each line of stdin to the daemon over one connection, and print the replies
+    Stdin,
+    /// Instruct the running daemon to shutdown
+    Shutdown,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DaemonArgs {
+    #[command(flatten)]
+    pub placement: PlacementArgs,
+
+    #[command(flatten)]
+    pub size: SizeArgs,
+
+    #[command(flatten)]
+    pub fullscreen: FullscreenArgs,
+
+    #[command(flatten)]
+    pub options: DaemonOptions,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MoveArgs {
+    #[command(flatten)]
+    pub placement: PlacementArgs,
+
+    #[command(flatten)]
+    pub size: SizeArgs,
+
+    #[command(flatten)]
+    pub target: TargetArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ResizeArgs {
+    #[command(flatten)]
+    pub size: SizeArgs,
+
+    #[command(flatten)]
+    pub target: TargetArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct FloatArgs {
+    /// Make the window sticky as well
+    #[arg(long)]
+    pub sticky: bool,
+
+    #[command(flatten)]
+    pub placement: PlacementArgs,
+
+    #[command(flatten)]
+    pub size: SizeArgs,
+}
+
+/// Where the window goes
+#[derive(Debug, Default, clap::Args)]
+pub struct PlacementArgs {
     /// The vertical third of the screen to place the window in
     pub vertical: Option<Vertical>,
     /// The horizontal third of the screen to place the window in
     pub horizontal: Option<Horizontal>,
 
     /// The amount of padding to add around moved window
+    ///
+    /// Percentages are relative to the shorter side of the available area.
     #[arg(short, long)]
-    pub padding: Option<u32>,
+    pub padding: Option<AbsoluteUnit>,
+
+    /// How to arrange the floating windows on the workspace
+    #[arg(long, value_enum)]
+    pub layout: Option<Layout>,
+
+    /// Move the window this far from its anchor, to the right for positive values
+    ///
+    /// Percentages are relative to the width of the available area (ex: `30%` or `-200px`).
+    #[arg(long, allow_hyphen_values = true)]
+    pub offset_x: Option<RelativeUnit>,
+
+    /// Move the window this far from its anchor, downwards for positive values
+    ///
+    /// Percentages are relative to the height of the available area.
+    #[arg(long, allow_hyphen_values = true)]
+    pub offset_y: Option<RelativeUnit>,
 
+    /// Nudge the window sideways by this much, on top of any earlier nudges (ex: `+20px`)
+    ///
+    /// Nudges start over whenever a new position or offset is given.
+    #[arg(long, allow_hyphen_values = true)]
+    pub nudge_x: Option<RelativeUnit>,
+
+    /// Nudge the window up or down by this much, on top of any earlier nudges
+    #[arg(long, allow_hyphen_values = true)]
+    pub nudge_y: Option<RelativeUnit>,
+}
+
+/// How big the window is
+#[derive(Debug, Default, clap::Args)]
+pub struct SizeArgs {
     /// Resize the window to this width
     #[arg(long, value_enum, allow_hyphen_values = true)]
     pub width: Option<Unit>,
@@ -46,29 +249,196 @@ pub struct Args {
     #[arg(long)]
     pub natural: Option<bool>,
 
-    /// Run as a daemon, and wait for events via IPC
-    #[arg(short, long)]
-    pub daemon: bool,
+    /// Lock the window to this aspect ratio (ex: `16:9`, `1.777`, or `none` to unlock)
+    ///
+    /// An explicit aspect ratio takes precedence over `--natural`. It is only used when one of
+    /// `width` or `height` is provided.
+    #[arg(long)]
+    pub aspect: Option<Aspect>,
+}
 
-    /// The path to use for the socket to listen on
-    #[arg(short, long, default_value = DEFAULT_SOCKET.as_str())]
-    pub socket: PathBuf,
+/// What happens to the window while another one is fullscreen
+#[derive(Debug, Default, clap::Args)]
+pub struct FullscreenArgs {
+    /// How to react when another window on the same output goes fullscreen
+    #[arg(long, value_enum)]
+    pub on_fullscreen: Option<FullscreenAction>,
+
+    /// The width to shrink the window to while another window is fullscreen
+    #[arg(long)]
+    pub fullscreen_width: Option<AbsoluteUnit>,
+
+    /// The vertical third to move the window to while another window is fullscreen
+    #[arg(long)]
+    pub fullscreen_vertical: Option<Vertical>,
+
+    /// The horizontal third to move the window to while another window is fullscreen
+    #[arg(long)]
+    pub fullscreen_horizontal: Option<Horizontal>,
+}
+
+/// How the daemon runs, besides its initial state
+#[derive(Debug, clap::Args)]
+pub struct DaemonOptions {
+    /// Also accept commands from this user ID, besides the user running the daemon
+    #[arg(long, value_name = "UID")]
+    pub allow_uid: Vec<u32>,
 
-    /// Delay (in milliseconds) to wait before processing events from the sway IPC
+    /// How long (in milliseconds) sway must be quiet before reacting to its events
     ///
-    /// This is mainly for allowing sway to settle after a reload or other event.
+    /// Bursts of events (like after a reload) are collapsed, and only handled once no new events
+    /// have arrived for this long and the workspaces have stopped changing size.
     #[arg(long, default_value_t = 200)]
     pub sway_event_delay: u64,
+}
 
-    /// Instruct the running daemon to shutdown
-    #[arg(long)]
-    pub shutdown: bool,
+/// Which window an update applies to, and how it is applied
+#[derive(Debug, Default, clap::Args)]
+pub struct TargetArgs {
+    /// Apply the update to the window in this named slot, instead of the targeted window
+    ///
+    /// The first time a slot is used, the targeted window is claimed for it with the sway mark
+    /// `_gravity_<slot>`. Each slot keeps its own state. Clearing the mark releases the window.
+    #[arg(long, value_parser = parse_slot_name)]
+    pub slot: Option<String>,
+
+    /// Print where the window would be placed, and the sway commands that would do it, without
+    /// moving anything
+    ///
+    /// The daemon only reports its own state, not the ones of its slots, so this doesn't work
+    /// with `--slot`.
+    #[arg(long, conflicts_with = "slot")]
+    pub dry_run: bool,
+
+    /// Place the window directly, without a daemon
+    ///
+    /// Useful from scripts, or before the daemon is started. Options that aren't given fall back to
+    /// the state saved in `--state-file`, or to their defaults without one.
+    #[arg(long, conflicts_with_all = ["dry_run", "slot"])]
+    pub oneshot: bool,
+}
+
+#[derive(Debug, Default, clap::Args)]
+pub struct LogArgs {
+    /// Only log messages at or above this level [env: SWAY_GRAVITY_LOG] [default: info]
+    #[arg(long, global = true, value_enum)]
+    pub log_level: Option<Level>,
+
+    /// Append log messages to this file instead of stderr
+    #[arg(long, global = true, conflicts_with = "syslog")]
+    pub log_file: Option<PathBuf>,
+
+    /// Send log messages to syslog instead of stderr
+    #[arg(long, global = true)]
+    pub syslog: bool,
 }
 
-static DEFAULT_SOCKET: LazyLock<String> = LazyLock::new(|| {
-    format!(
-        "{}/sway-gravity/{}.sock",
-        env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "./".to_string()),
-        env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "sway".to_string())
-    )
-});
+impl Args {
+    /// Fold the subcommand into the flags of the shorthand form, which the rest of the program
+    /// works with
+    pub fn normalize(mut self) -> Self {
+        match self.command.take() {
+            None => {}
+            Some(Command::Daemon(daemon)) => {
+                self.daemon = true;
+                self.placement = daemon.placement;
+                self.size = daemon.size;
+                self.fullscreen = daemon.fullscreen;
+                self.daemon_options = daemon.options;
+            }
+            Some(Command::Move(update)) => {
+                self.placement = update.placement;
+                self.size = update.size;
+                self.target = update.target;
+            }
+            Some(Command::Resize(update)) => {
+                self.size = update.size;
+                self.target = update.target;
+            }
+            Some(Command::Float(float)) => {
+                self.float = true;
+                self.sticky = float.sticky;
+                self.placement = float.placement;
+                self.size = float.size;
+            }
+            Some(Command::Unfloat) => self.unfloat = true,
+            Some(Command::Scratchpad { action }) => self.scratchpad = Some(action),
+            Some(Command::Status) => self.status = true,
+            Some(Command::ListDaemons) => self.list_daemons = true,
+            Some(Command::Batch) => self.batch = true,
+            Some(Command::Stdin) => self.stdin = true,
+            Some(Command::Shutdown) => self.shutdown = true,
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::try_parse_from(std::iter::once("sway-gravity").chain(args.iter().copied()))
+            .unwrap()
+            .normalize()
+    }
+
+    #[test]
+    fn test_args_definition() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_shorthand_and_subcommands() {
+        let shorthand = parse(&["bottom", "right", "--width", "35%"]);
+        let subcommand = parse(&["move", "bottom", "right", "--width", "35%"]);
+        for args in [shorthand, subcommand] {
+            assert!(matches!(args.placement.vertical, Some(Vertical::Bottom)));
+            assert!(matches!(args.placement.horizontal, Some(Horizontal::Right)));
+            assert!(args.size.width.is_some());
+            assert!(!args.daemon);
+        }
+
+        let args = parse(&[
+            "daemon",
+            "--width",
+            "35%",
+            "--padding",
+            "12",
+            "bottom",
+            "right",
+        ]);
+        assert!(args.daemon);
+        assert!(args.placement.padding.is_some());
+
+        // both forms share the same daemon options
+        for args in [
+            parse(&["daemon", "--allow-uid", "1001", "--sway-event-delay", "50"]),
+            parse(&["-d", "--allow-uid", "1001", "--sway-event-delay", "50"]),
+        ] {
+            assert_eq!(args.daemon_options.allow_uid, vec![1001]);
+            assert_eq!(args.daemon_options.sway_event_delay, 50);
+        }
+        assert_eq!(parse(&["daemon"]).daemon_options.sway_event_delay, 200);
+        assert!(parse(&["-d", "--width", "35%", "bottom", "right"]).daemon);
+
+        assert!(parse(&["status"]).status);
+        assert!(parse(&["--status"]).status);
+        assert!(parse(&["shutdown", "--socket", "/tmp/gravity.sock"]).shutdown);
+        assert_eq!(
+            parse(&["resize", "--width", "+5%", "--slot", "pip"])
+                .target
+                .slot
+                .as_deref(),
+            Some("pip")
+        );
+
+        assert!(Args::try_parse_from(["sway-gravity", "--dry-run", "--slot", "pip"]).is_err());
+
+        // flags of the shorthand form don't mix with subcommands
+        assert!(Args::try_parse_from(["sway-gravity", "--width", "5%", "status"]).is_err());
+    }
+}
@@ -1,17 +1,41 @@
 use std::{
     error::Error,
     fmt::Display,
-    io::{self, Write},
+    io::{self, BufRead, BufReader, Write},
+    net::Shutdown,
     os::unix::net::UnixStream,
     path::PathBuf,
 };
 
-use crate::daemon::DaemonEvent;
+use clap::Parser;
+
+use crate::{
+    cli::Args,
+    daemon::{
+        batch::BatchStep,
+        protocol::{Hello, Request, Response, PROTOCOL_VERSION},
+        status::DaemonStatus,
+        DaemonEvent,
+    },
+    debug,
+    discovery::{find_sockets, format_uptime, socket_dir},
+    error,
+};
 
 #[derive(Debug)]
 pub enum ClientError {
     IoError(io::Error),
     InvalidMessage(serde_json::Error),
+    /// The daemon closed the connection without replying, which daemons before the versioned
+    /// protocol do
+    NoReply,
+    /// The daemon speaks a different version of the protocol
+    VersionMismatch(u32),
+    /// The daemon doesn't know about this kind of event
+    Unsupported(&'static str),
+    Rejected(String),
+    /// A line of a batch isn't an update
+    InvalidBatchStep(String),
 }
 
 impl Display for ClientError {
@@ -19,6 +43,25 @@ impl Display for ClientError {
         match self {
             ClientError::IoError(err) => write!(f, "IO error: {}", err),
             ClientError::InvalidMessage(err) => write!(f, "Message encoding error: {}", err),
+            ClientError::NoReply => write!(
+                f,
+                "The daemon didn't reply, it is probably older than this client. Restart it (ex: \
+                 by reloading sway) to update it"
+            ),
+            ClientError::VersionMismatch(supported) => write!(
+                f,
+                "The daemon speaks protocol version {}, but this client speaks version {}. \
+                 Restart the daemon (ex: by reloading sway) so both are the same version",
+                supported, PROTOCOL_VERSION
+            ),
+            ClientError::Unsupported(kind) => write!(
+                f,
+                "The daemon doesn't support `{}` yet. Restart it (ex: by reloading sway) to \
+                 update it",
+                kind
+            ),
+            ClientError::Rejected(err) => write!(f, "The daemon rejected the message: {}", err),
+            ClientError::InvalidBatchStep(err) => write!(f, "Invalid batch step: {}", err),
         }
     }
 }
@@ -28,6 +71,7 @@ impl Error for ClientError {
         match self {
             ClientError::IoError(err) => Some(err),
             ClientError::InvalidMessage(err) => Some(err),
+            _ => None,
         }
     }
 }
@@ -45,9 +89,261 @@ impl From<serde_json::Error> for ClientError {
 }
 
 pub fn send_message(socket: &PathBuf, event: DaemonEvent) -> Result<(), ClientError> {
-    eprintln!("Sending message to {}", socket.display());
+    let kind = event.kind();
+
+    match send_request(socket, event)? {
+        Response::Ok => Ok(()),
+        Response::Error(err) => {
+            // the daemon might just be older than us, which the handshake can tell
+            match handshake(socket) {
+                Ok(hello) if !hello.capabilities.iter().any(|c| c == kind) => {
+                    Err(ClientError::Unsupported(kind))
+                }
+                _ => Err(ClientError::Rejected(err)),
+            }
+        }
+        response => Err(unexpected(response)),
+    }
+}
+
+/// Ask the daemon for its status, and wait for the reply
+pub fn request_status(socket: &PathBuf) -> Result<DaemonStatus, ClientError> {
+    match send_request(socket, DaemonEvent::Status)? {
+        Response::Status(status) => Ok(*status),
+        response => Err(unexpected(response)),
+    }
+}
+
+/// Ask the daemon which protocol version and events it supports
+pub fn handshake(socket: &PathBuf) -> Result<Hello, ClientError> {
+    match send_request(socket, DaemonEvent::Hello)? {
+        Response::Hello(hello) => Ok(hello),
+        response => Err(unexpected(response)),
+    }
+}
+
+fn send_request(socket: &PathBuf, event: DaemonEvent) -> Result<Response, ClientError> {
+    debug!(socket = socket; "Sending message");
     let mut socket = UnixStream::connect(socket)?;
 
-    let message = serde_json::to_string(&event).expect("message should be serializable");
-    Ok(socket.write_all(message.as_bytes())?)
+    socket.write_all(&encode(event))?;
+    // we only have the one message, and daemons from before json lines wait for the end of it
+    socket.shutdown(Shutdown::Write)?;
+
+    match serde_json::from_reader(socket) {
+        Ok(response) => Ok(response),
+        Err(e) if e.is_eof() => Err(ClientError::NoReply),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Forward every line of stdin to the daemon over a single connection, printing each reply
+///
+/// Lines can either be events as JSON, or the same arguments that the client takes (ex:
+/// `bottom right --width 30%`).
+pub fn forward_stdin(socket: &PathBuf) -> Result<(), ClientError> {
+    let hello = handshake(socket)?;
+    if !hello.capabilities.iter().any(|c| c == "json-lines") {
+        return Err(ClientError::Unsupported("json-lines"));
+    }
+
+    let mut connection = UnixStream::connect(socket)?;
+    let mut replies = BufReader::new(connection.try_clone()?).lines();
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event = match parse_line(&line) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Skipping invalid line: {}", e);
+                continue;
+            }
+        };
+
+        connection.write_all(&encode(event))?;
+        match replies.next() {
+            Some(reply) => println!("{}", reply?),
+            None => return Err(ClientError::NoReply),
+        }
+    }
+
+    Ok(())
+}
+
+/// Send every line of stdin to the daemon as one batch, which it applies all at once
+pub fn send_batch(socket: &PathBuf) -> Result<(), ClientError> {
+    let mut steps = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // a batch is all or nothing, so a bad line fails the whole batch
+        let step = parse_line(&line)
+            .and_then(batch_step)
+            .map_err(ClientError::InvalidBatchStep)?;
+        steps.push(step);
+    }
+
+    send_message(socket, DaemonEvent::Batch(steps))
+}
+
+fn batch_step(event: DaemonEvent) -> Result<BatchStep, String> {
+    match event {
+        DaemonEvent::Update(update) => Ok(BatchStep { slot: None, update }),
+        DaemonEvent::Slot(slot) => Ok(BatchStep {
+            slot: Some(slot.name),
+            update: slot.update,
+        }),
+        event => Err(format!("{} can't be part of a batch", event.kind())),
+    }
+}
+
+/// A request, on its own line
+fn encode(event: DaemonEvent) -> Vec<u8> {
+    let mut message =
+        serde_json::to_vec(&Request::new(event)).expect("message should be serializable");
+    message.push(b'\n');
+    message
+}
+
+fn parse_line(line: &str) -> Result<DaemonEvent, String> {
+    let line = line.trim();
+    if line.starts_with('{') || line.starts_with('"') {
+        return serde_json::from_str(line).map_err(|e| e.to_string());
+    }
+
+    let args =
+        Args::try_parse_from(std::iter::once("sway-gravity".to_string()).chain(split_args(line)?))
+            .map_err(|e| e.to_string())?
+            .normalize();
+    if args.daemon
+        || args.stdin
+        || args.batch
+        || args.target.oneshot
+        || args.target.dry_run
+        || args.list_daemons
+    {
+        return Err("only messages for the daemon can be sent this way".to_string());
+    }
+
+    Ok(args.into())
+}
+
+/// Split a line into arguments like a shell would, minus everything but quoting
+fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+    args.extend(current);
+
+    Ok(args)
+}
+
+fn unexpected(response: Response) -> ClientError {
+    match response {
+        Response::UnsupportedVersion { supported } => ClientError::VersionMismatch(supported),
+        Response::Error(err) => ClientError::Rejected(err),
+        response => ClientError::Rejected(format!("Unexpected response: {:?}", response)),
+    }
+}
+
+/// Print every daemon with a socket in the socket directory
+pub fn list_daemons() {
+    let sockets = find_sockets(&socket_dir());
+    if sockets.is_empty() {
+        println!("No daemons found in {}", socket_dir().display());
+    }
+
+    for socket in sockets {
+        match request_status(&socket) {
+            Ok(status) => println!(
+                "{}\tpid {}\tup {}\tsway {}",
+                socket.display(),
+                status.pid,
+                format_uptime(status.uptime()),
+                status.sway_socket.as_deref().unwrap_or("unknown"),
+            ),
+            // most likely left behind by a daemon that didn't get to clean up
+            Err(e) => println!("{}\tnot responding ({})", socket.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args("bottom right  --width 30%").unwrap(),
+            vec!["bottom", "right", "--width", "30%"]
+        );
+        assert_eq!(
+            split_args(r#"--width 'calc(50% - 20px)' --slot "" "#).unwrap(),
+            vec!["--width", "calc(50% - 20px)", "--slot", ""]
+        );
+        assert!(split_args("--width 'calc(50%").is_err());
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert!(matches!(
+            parse_line("bottom right --width 30%"),
+            Ok(DaemonEvent::Update(_))
+        ));
+        assert!(matches!(
+            parse_line(r#""Unfloat""#),
+            Ok(DaemonEvent::Unfloat)
+        ));
+        assert!(matches!(
+            parse_line(r#"{"Scratchpad": "toggle"}"#),
+            Ok(DaemonEvent::Scratchpad(_))
+        ));
+        assert!(parse_line("-d").is_err());
+        assert!(parse_line("--batch").is_err());
+        assert!(matches!(
+            parse_line("move top left --width 30%"),
+            Ok(DaemonEvent::Update(_))
+        ));
+        assert!(matches!(parse_line("unfloat"), Ok(DaemonEvent::Unfloat)));
+        assert!(parse_line("daemon").is_err());
+        assert!(parse_line("sideways").is_err());
+    }
+
+    #[test]
+    fn test_batch_step() {
+        let step = batch_step(parse_line("--slot pip top left --padding 0").unwrap()).unwrap();
+        assert_eq!(step.slot.as_deref(), Some("pip"));
+        assert!(step.update.padding.is_some());
+
+        let step = batch_step(parse_line("bottom right").unwrap()).unwrap();
+        assert_eq!(step.slot, None);
+
+        assert!(batch_step(DaemonEvent::Unfloat).is_err());
+        assert!(batch_step(DaemonEvent::Batch(vec![])).is_err());
+    }
 }
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use swayipc::Node;
+
+use crate::{
+    compute_placement,
+    daemon::{
+        slot::{deserialize_optional_slot_name, Slots},
+        state::{StateUpdate, StateUpdateError},
+    },
+    debug, find_target_node,
+    sway::{mark_action, CommandError, SwayConnection},
+    warn, State,
+};
+
+/// One step of a batch, targeting the window in a slot, or the usual window without one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchStep {
+    #[serde(default, deserialize_with = "deserialize_optional_slot_name")]
+    pub slot: Option<String>,
+    pub update: StateUpdate,
+}
+
+/// Apply every step in one go
+///
+/// All placements are computed before anything is sent, and then sent to sway as a single command
+/// list so windows aren't redrawn in between. The stored states are only updated once every
+/// command succeeded. Sway keeps the commands that did succeed, so when one fails the targeted
+/// windows are placed according to their previous state again, as far as sway lets us.
+pub fn run_batch(
+    con: &mut SwayConnection,
+    state: &mut State,
+    slots: &mut Slots,
+    steps: Vec<BatchStep>,
+) -> Result<(), StateUpdateError> {
+    let mut pending = state.clone();
+    let mut pending_slots: HashMap<String, State> = HashMap::new();
+    let mut commands = Vec::with_capacity(steps.len());
+    // the state each window was placed with before the batch, to put it back if the batch fails
+    let mut previous: Vec<(Node, State)> = Vec::new();
+
+    for step in steps {
+        let command = match step.slot {
+            Some(name) => {
+                let (target, claim) = slots.target(con, &name)?;
+                let slot_state = pending_slots
+                    .remove(&name)
+                    .unwrap_or_else(|| slots.state(&name, state));
+                remember(&mut previous, &target, slots.state(&name, state));
+                let placement = compute_placement(con, &target, slot_state, step.update)?;
+
+                let claim: Vec<String> = claim.as_deref().map(mark_action).into_iter().collect();
+                let command = placement.command(&claim);
+                pending_slots.insert(name, placement.state);
+                command
+            }
+            None => {
+                let target = find_target_node(con)?;
+                remember(&mut previous, &target, state.clone());
+                let placement = compute_placement(con, &target, pending, step.update)?;
+                let command = placement.command(&[]);
+                pending = placement.state;
+                command
+            }
+        };
+        commands.push(command);
+    }
+
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let commands = commands.join("; ");
+    debug!(commands = commands; "Running batch");
+    if let Err(e) = con.run_commands(&commands) {
+        if let CommandError::Rejected { .. } = e {
+            roll_back(con, previous);
+        }
+        return Err(e.into());
+    }
+
+    *state = pending;
+    for (name, slot_state) in pending_slots {
+        slots.set_state(name, slot_state);
+    }
+
+    Ok(())
+}
+
+fn remember(previous: &mut Vec<(Node, State)>, target: &Node, state: State) {
+    if !previous.iter().any(|(node, _)| node.id == target.id) {
+        previous.push((target.clone(), state));
+    }
+}
+
+/// Place the windows of a failed batch according to the states they had before it
+fn roll_back(con: &mut SwayConnection, previous: Vec<(Node, State)>) {
+    let mut commands = Vec::with_capacity(previous.len());
+    for (node, state) in previous {
+        match compute_placement(con, &node, state, StateUpdate::default()) {
+            Ok(placement) => commands.push(placement.command(&[])),
+            Err(e) => {
+                warn!(con_id = node.id; "Failed to compute where to put the window back: {}", e)
+            }
+        }
+    }
+
+    if commands.is_empty() {
+        return;
+    }
+    if let Err(e) = con.run_commands(&commands.join("; ")) {
+        warn!("Failed to roll back the batch: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_batch_step() {
+        let step: BatchStep =
+            serde_json::from_str(r#"{"update": {"position": [null, null]}}"#).unwrap();
+        assert_eq!(step.slot, None);
+
+        let step: BatchStep =
+            serde_json::from_str(r#"{"slot": "pip", "update": {"position": [null, null]}}"#)
+                .unwrap();
+        assert_eq!(step.slot.as_deref(), Some("pip"));
+
+        let injected = r#"{"slot": "x, exec foo", "update": {"position": [null, null]}}"#;
+        assert!(serde_json::from_str::<BatchStep>(injected).is_err());
+    }
+}
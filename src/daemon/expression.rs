@@ -0,0 +1,392 @@
+use std::{fmt::Display, iter::Peekable, str::Chars, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::unit::{Density, ParseUnitError, PhysicalLength, PhysicalUnit};
+
+const FUNCTIONS: [&str; 4] = ["calc", "min", "max", "clamp"];
+
+/// A CSS-like size expression, evaluated against the size of the container (ex:
+/// `calc(50% - 20px)`, `min(40%, 800px)`, `max(...)` or `clamp(200px, 30%, 600px)`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Expression {
+    Pixels(f32),
+    Percentage(f32),
+    Physical(PhysicalLength),
+    DevicePixels(f32),
+    Add(Box<Expression>, Box<Expression>),
+    Sub(Box<Expression>, Box<Expression>),
+    Mul(Box<Expression>, f32),
+    Div(Box<Expression>, f32),
+    Min(Vec<Expression>),
+    Max(Vec<Expression>),
+    Clamp(Box<Expression>, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Whether the given string looks like an expression rather than a plain unit
+    pub fn is_expression(s: &str) -> bool {
+        let s = s.trim_start();
+        FUNCTIONS
+            .iter()
+            .any(|name| s.strip_prefix(name).is_some_and(|s| s.starts_with('(')))
+    }
+
+    /// Evaluate the expression to a number of pixels
+    pub fn evaluate(&self, container_px: i32) -> f32 {
+        match self {
+            Self::Pixels(pixels) => *pixels,
+            Self::Percentage(percentage) => container_px as f32 * (percentage / 100.0),
+            Self::Physical(length) => length.as_pixels(&Density::default()),
+            Self::DevicePixels(pixels) => *pixels,
+            Self::Add(a, b) => a.evaluate(container_px) + b.evaluate(container_px),
+            Self::Sub(a, b) => a.evaluate(container_px) - b.evaluate(container_px),
+            Self::Mul(a, factor) => a.evaluate(container_px) * factor,
+            Self::Div(a, divisor) => a.evaluate(container_px) / divisor,
+            Self::Min(args) => args
+                .iter()
+                .map(|arg| arg.evaluate(container_px))
+                .fold(f32::INFINITY, f32::min),
+            Self::Max(args) => args
+                .iter()
+                .map(|arg| arg.evaluate(container_px))
+                .fold(f32::NEG_INFINITY, f32::max),
+            // like CSS, the minimum wins if it is larger than the maximum
+            Self::Clamp(min, value, max) => value
+                .evaluate(container_px)
+                .min(max.evaluate(container_px))
+                .max(min.evaluate(container_px)),
+        }
+    }
+
+    /// Convert any physical lengths and device pixels into (logical) pixels, using the density
+    /// and scale of the output
+    pub fn resolve(&self, density: &Density) -> Self {
+        let resolve = |expression: &Expression| Box::new(expression.resolve(density));
+
+        match self {
+            Self::Physical(length) => Self::Pixels(length.as_pixels(density)),
+            Self::DevicePixels(pixels) => Self::Pixels(pixels / density.scale),
+            Self::Pixels(_) | Self::Percentage(_) => self.clone(),
+            Self::Add(a, b) => Self::Add(resolve(a), resolve(b)),
+            Self::Sub(a, b) => Self::Sub(resolve(a), resolve(b)),
+            Self::Mul(a, factor) => Self::Mul(resolve(a), *factor),
+            Self::Div(a, divisor) => Self::Div(resolve(a), *divisor),
+            Self::Min(args) => Self::Min(args.iter().map(|arg| arg.resolve(density)).collect()),
+            Self::Max(args) => Self::Max(args.iter().map(|arg| arg.resolve(density)).collect()),
+            Self::Clamp(min, value, max) => Self::Clamp(resolve(min), resolve(value), resolve(max)),
+        }
+    }
+
+    fn is_sum(&self) -> bool {
+        matches!(self, Self::Add(..) | Self::Sub(..))
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !Self::is_expression(s) {
+            return Err(ParseUnitError::InvalidExpression(s.to_string()));
+        }
+
+        let mut parser = Parser::new(s);
+        let expression = parser.sum()?;
+        parser.end()?;
+
+        Ok(expression)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Min(_) | Self::Max(_) | Self::Clamp(..) => write!(f, "{}", Inner(self)),
+            _ => write!(f, "calc({})", Inner(self)),
+        }
+    }
+}
+
+/// Displays an expression without the surrounding `calc()`
+struct Inner<'a>(&'a Expression);
+
+impl Display for Inner<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |f: &mut std::fmt::Formatter<'_>, args: &[&Expression]| {
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", Inner(arg))?;
+            }
+            Ok(())
+        };
+
+        match self.0 {
+            Expression::Pixels(pixels) => write!(f, "{}px", pixels),
+            Expression::Percentage(percentage) => write!(f, "{}%", percentage),
+            Expression::Physical(length) => write!(f, "{}", length),
+            Expression::DevicePixels(pixels) => write!(f, "{}dpx", pixels),
+            Expression::Add(a, b) => write!(f, "{} + {}", Inner(a), Inner(b)),
+            Expression::Sub(a, b) if b.is_sum() => write!(f, "{} - ({})", Inner(a), Inner(b)),
+            Expression::Sub(a, b) => write!(f, "{} - {}", Inner(a), Inner(b)),
+            Expression::Mul(a, factor) if a.is_sum() => write!(f, "({}) * {}", Inner(a), factor),
+            Expression::Mul(a, factor) => write!(f, "{} * {}", Inner(a), factor),
+            Expression::Div(a, divisor) if a.is_sum() => write!(f, "({}) / {}", Inner(a), divisor),
+            Expression::Div(a, divisor) => write!(f, "{} / {}", Inner(a), divisor),
+            Expression::Min(args) => {
+                write!(f, "min(")?;
+                list(f, &args.iter().collect::<Vec<_>>())?;
+                write!(f, ")")
+            }
+            Expression::Max(args) => {
+                write!(f, "max(")?;
+                list(f, &args.iter().collect::<Vec<_>>())?;
+                write!(f, ")")
+            }
+            Expression::Clamp(min, value, max) => {
+                write!(f, "clamp(")?;
+                list(f, &[min, value, max])?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// An operand is either a size, or a plain number that can only be used as a factor
+enum Operand {
+    Size(Expression),
+    Number(f32),
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.chars().peekable(),
+        }
+    }
+
+    fn error(&self) -> ParseUnitError {
+        ParseUnitError::InvalidExpression(self.source.to_string())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseUnitError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.chars.next();
+                Ok(())
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ParseUnitError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error()),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, ParseUnitError> {
+        let mut expression = self.size()?;
+
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.chars.next();
+            let rhs = Box::new(self.size()?);
+            let lhs = Box::new(expression);
+
+            expression = match op {
+                '+' => Expression::Add(lhs, rhs),
+                _ => Expression::Sub(lhs, rhs),
+            };
+        }
+
+        Ok(expression)
+    }
+
+    /// A product that must end up with a size, ex: `50%`, `50% * 2` or `2 * 50%`
+    fn size(&mut self) -> Result<Expression, ParseUnitError> {
+        match self.product()? {
+            Operand::Size(expression) => Ok(expression),
+            Operand::Number(_) => Err(self.error()),
+        }
+    }
+
+    fn product(&mut self) -> Result<Operand, ParseUnitError> {
+        let mut operand = self.operand()?;
+
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.chars.next();
+            let rhs = self.operand()?;
+
+            operand = match (operand, rhs, op) {
+                (Operand::Number(a), Operand::Number(b), '*') => Operand::Number(a * b),
+                (Operand::Number(a), Operand::Number(b), _) => Operand::Number(a / b),
+                (Operand::Size(a), Operand::Number(b), '*')
+                | (Operand::Number(b), Operand::Size(a), '*') => {
+                    Operand::Size(Expression::Mul(Box::new(a), b))
+                }
+                (Operand::Size(a), Operand::Number(b), _) if b != 0.0 => {
+                    Operand::Size(Expression::Div(Box::new(a), b))
+                }
+                _ => return Err(self.error()),
+            };
+        }
+
+        Ok(operand)
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseUnitError> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let expression = self.sum()?;
+                self.expect(')')?;
+                Ok(Operand::Size(expression))
+            }
+            Some('-') => {
+                self.chars.next();
+                Ok(match self.operand()? {
+                    Operand::Size(expression) => {
+                        Operand::Size(Expression::Mul(Box::new(expression), -1.0))
+                    }
+                    Operand::Number(number) => Operand::Number(-number),
+                })
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.length(),
+            Some(c) if c.is_ascii_alphabetic() => self.function().map(Operand::Size),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn length(&mut self) -> Result<Operand, ParseUnitError> {
+        let mut number = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+            number.push(c);
+        }
+        let value: f32 = number.parse()?;
+
+        if self.chars.next_if_eq(&'%').is_some() {
+            return Ok(Operand::Size(Expression::Percentage(value)));
+        }
+
+        match self.word().as_str() {
+            "px" => Ok(Operand::Size(Expression::Pixels(value))),
+            "dpx" => Ok(Operand::Size(Expression::DevicePixels(value))),
+            "" => Ok(Operand::Number(value)),
+            suffix => match PhysicalUnit::from_suffix(suffix) {
+                Some(unit) => Ok(Operand::Size(Expression::Physical(PhysicalLength {
+                    value,
+                    unit,
+                }))),
+                None => Err(self.error()),
+            },
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+            word.push(c);
+        }
+        word
+    }
+
+    fn function(&mut self) -> Result<Expression, ParseUnitError> {
+        let name = self.word();
+        self.expect('(')?;
+
+        let mut args = vec![self.sum()?];
+        while self.peek() == Some(',') {
+            self.chars.next();
+            args.push(self.sum()?);
+        }
+        self.expect(')')?;
+
+        match (name.as_str(), args.len()) {
+            ("calc", 1) => Ok(args.remove(0)),
+            ("min", _) => Ok(Expression::Min(args)),
+            ("max", _) => Ok(Expression::Max(args)),
+            ("clamp", 3) => {
+                let max = Box::new(args.remove(2));
+                let value = Box::new(args.remove(1));
+                let min = Box::new(args.remove(0));
+                Ok(Expression::Clamp(min, value, max))
+            }
+            _ => Err(self.error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(s: &str, container_px: i32) -> f32 {
+        Expression::from_str(s).unwrap().evaluate(container_px)
+    }
+
+    #[test]
+    fn test_evaluate_expressions() {
+        assert_eq!(evaluate("calc(50% - 20px)", 1000), 480.0);
+        assert_eq!(evaluate("calc(50% + 20px)", 1000), 520.0);
+        assert_eq!(evaluate("calc(100% / 3)", 900), 300.0);
+        assert_eq!(evaluate("calc(2 * 10% - (5px + 5px))", 1000), 190.0);
+        assert_eq!(evaluate("calc(-20px + 50%)", 1000), 480.0);
+        assert_eq!(evaluate("min(40%, 800px)", 1000), 400.0);
+        assert_eq!(evaluate("min(40%, 800px)", 3000), 800.0);
+        assert_eq!(evaluate("max(40%, 800px)", 1000), 800.0);
+        assert_eq!(evaluate("clamp(200px, 30%, 600px)", 1000), 300.0);
+        assert_eq!(evaluate("clamp(200px, 30%, 600px)", 100), 200.0);
+        assert_eq!(evaluate("clamp(200px, 30%, 600px)", 3000), 600.0);
+        assert_eq!(evaluate("calc(min(40%, 800px) - 20px)", 3000), 780.0);
+        assert_eq!(evaluate("calc(1in + 4px)", 1000), 100.0);
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(Expression::from_str("50% - 20px").is_err());
+        assert!(Expression::from_str("calc(50% - )").is_err());
+        assert!(Expression::from_str("calc(50% 20px)").is_err());
+        assert!(Expression::from_str("calc(50% * 10%)").is_err());
+        assert!(Expression::from_str("calc(50% / 0)").is_err());
+        assert!(Expression::from_str("calc(2)").is_err());
+        assert!(Expression::from_str("calc(20em)").is_err());
+        assert!(Expression::from_str("clamp(1px, 2px)").is_err());
+        assert!(Expression::from_str("min(40%, 800px").is_err());
+    }
+
+    #[test]
+    fn test_expression_display_round_trip() {
+        for s in [
+            "calc(50% - 20px)",
+            "calc(50% - (10px + 10px))",
+            "calc((50% + 10px) * 2)",
+            "min(40%, 800px)",
+            "clamp(200px, 30%, 100% - 20px)",
+        ] {
+            let expression = Expression::from_str(s).unwrap();
+            assert_eq!(expression.to_string(), s);
+            assert_eq!(
+                Expression::from_str(&expression.to_string()).unwrap(),
+                expression
+            );
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use swayipc::NodeType;
+
+use crate::{
+    daemon::state::{State, StateUpdate, StateUpdateError},
+    find_target_node, move_window_with,
+    sway::{node_command, SwayConnection},
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FloatRequest {
+    pub sticky: bool,
+    pub update: StateUpdate,
+}
+
+/// Where a floated window used to be tiled
+#[derive(Debug, Clone)]
+struct TilingSpot {
+    workspace: String,
+    /// Whether the marked sibling was before the window, or after it
+    after_sibling: Option<bool>,
+}
+
+/// Remembers where the windows we floated were tiled, so they can be returned there later
+#[derive(Debug, Default)]
+pub struct TilingSpots {
+    spots: HashMap<i64, TilingSpot>,
+}
+
+impl TilingSpots {
+    pub fn float(
+        &mut self,
+        con: &mut SwayConnection,
+        state: &mut State,
+        request: FloatRequest,
+    ) -> Result<(), StateUpdateError> {
+        let tree = con.get_tree()?;
+        let target = tree
+            .find_focused_as_ref(|node| {
+                node.focused && matches!(node.node_type, NodeType::Con | NodeType::FloatingCon)
+            })
+            .ok_or(StateUpdateError::NoApplicableNode)?;
+        let target_id = target.id;
+
+        let mut commands = Vec::new();
+        if target.node_type == NodeType::Con {
+            let workspace = tree
+                .iter()
+                .find(|node| {
+                    node.node_type == NodeType::Workspace
+                        && node.iter().any(|node| node.id == target_id)
+                })
+                .and_then(|workspace| workspace.name.clone())
+                .ok_or(StateUpdateError::NoApplicableNode)?;
+
+            // mark a neighbour, so we can find our way back to the same spot
+            let siblings = tree
+                .find_as_ref(|node| node.nodes.iter().any(|node| node.id == target_id))
+                .map(|parent| parent.nodes.as_slice())
+                .unwrap_or_default();
+            let index = siblings.iter().position(|node| node.id == target_id);
+            let sibling = match index {
+                Some(index) if index > 0 => Some((&siblings[index - 1], true)),
+                Some(index) => siblings.get(index + 1).map(|sibling| (sibling, false)),
+                None => None,
+            };
+
+            if let Some((sibling, _)) = sibling {
+                con.mark_node(sibling.id, &return_mark(target_id))?;
+            }
+
+            self.spots.insert(
+                target_id,
+                TilingSpot {
+                    workspace,
+                    after_sibling: sibling.map(|(_, after)| after),
+                },
+            );
+            commands.push("floating enable".to_string());
+        }
+
+        // the window has to be floating before its placement can be computed
+        if !commands.is_empty() {
+            con.run_commands(&node_command(target_id, &commands))?;
+        }
+
+        let target = con
+            .find_node(target_id)?
+            .ok_or(StateUpdateError::NoApplicableNode)?;
+        let extra = if request.sticky {
+            vec!["sticky enable".to_string()]
+        } else {
+            vec![]
+        };
+        *state = move_window_with(con, target, state.clone(), request.update, &extra)?;
+
+        Ok(())
+    }
+
+    pub fn unfloat(&mut self, con: &mut SwayConnection) -> Result<(), StateUpdateError> {
+        let target = find_target_node(con)?;
+        let mark = return_mark(target.id);
+
+        let mut commands = vec!["sticky disable".to_string(), "floating disable".to_string()];
+        match self.spots.remove(&target.id) {
+            Some(TilingSpot {
+                after_sibling: Some(after),
+                ..
+            }) if con.has_mark(&mark)? => {
+                commands.push(format!("move container to mark {}", mark));
+                // moving to a mark places the window after it, so swap to end up before it
+                if !after {
+                    commands.push(format!("swap container with mark {}", mark));
+                }
+            }
+            Some(TilingSpot { workspace, .. }) => {
+                commands.push(format!("move container to workspace {}", workspace));
+            }
+            None => {}
+        }
+
+        con.run_commands(&node_command(target.id, &commands))?;
+        con.unmark(&mark)?;
+
+        Ok(())
+    }
+}
+
+fn return_mark(node_id: i64) -> String {
+    format!("_gravity_return_{}", node_id)
+}
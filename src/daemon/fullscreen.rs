@@ -0,0 +1,277 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::{
+        scratchpad::show_from_scratchpad,
+        state::{
+            Horizontal, Position, PositionUpdate, State, StateUpdate, StateUpdateError, Vertical,
+        },
+        unit::{AbsolutePercentage, AbsoluteUnit},
+    },
+    find_placed_node, find_target_node, move_window,
+    sway::SwayConnection,
+};
+
+/// What to do with the controlled window while another window on the same output is fullscreen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FullscreenAction {
+    /// Leave the window where it is
+    #[default]
+    Ignore,
+    /// Hide the window in the scratchpad
+    Hide,
+    /// Shrink the window to the fullscreen width
+    Shrink,
+    /// Move the window to the fullscreen position
+    Move,
+}
+
+#[derive(Debug, Clone)]
+pub struct FullscreenOptions {
+    pub action: FullscreenAction,
+    pub width: AbsoluteUnit,
+    pub position: Position,
+}
+
+impl Default for FullscreenOptions {
+    fn default() -> Self {
+        Self {
+            action: FullscreenAction::default(),
+            width: AbsolutePercentage(15.0).into(),
+            position: Position(Vertical::Top, Horizontal::Right),
+        }
+    }
+}
+
+impl FullscreenOptions {
+    pub fn update(
+        &mut self,
+        action: Option<FullscreenAction>,
+        width: Option<AbsoluteUnit>,
+        position: PositionUpdate,
+    ) {
+        if let Some(action) = action {
+            self.action = action;
+        }
+        if let Some(width) = width {
+            self.width = width;
+        }
+        self.position.update(position);
+    }
+
+    /// The state the window should take while another window is fullscreen
+    pub fn apply(&self, state: &State) -> State {
+        let mut reacted = state.clone();
+
+        match self.action {
+            FullscreenAction::Shrink => {
+                reacted.width = Some(self.width.clone());
+                reacted.height = None;
+            }
+            FullscreenAction::Move => reacted.position = self.position.clone(),
+            FullscreenAction::Ignore | FullscreenAction::Hide => {}
+        }
+
+        reacted
+    }
+}
+
+/// The state to go back to once the fullscreen window is gone
+///
+/// Only the fields the reaction changed are put back, and only if nothing updated them while the
+/// other window was fullscreen, so those updates are kept.
+fn restore(state: &State, saved: &State, reacted: &State) -> State {
+    let mut restored = state.clone();
+
+    if state.width == reacted.width {
+        restored.width = saved.width.clone();
+    }
+    if state.height == reacted.height {
+        restored.height = saved.height.clone();
+    }
+    if state.position == reacted.position {
+        restored.position = saved.position.clone();
+    }
+
+    restored
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FullscreenChange {
+    pub con_id: i64,
+    pub active: bool,
+    /// The window was closed, rather than only leaving fullscreen
+    #[serde(default)]
+    pub closed: bool,
+}
+
+/// Tracks the fullscreen window we are currently reacting to, so the previous state can be
+/// restored once it leaves fullscreen.
+#[derive(Debug, Default)]
+pub struct FullscreenTracker {
+    fullscreen_con: Option<i64>,
+    /// The window we reacted with
+    target: Option<i64>,
+    saved: Option<State>,
+    /// The state right after reacting, to tell which changes were made in the meantime
+    reacted: Option<State>,
+    hidden: Option<i64>,
+}
+
+impl FullscreenTracker {
+    pub fn handle(
+        &mut self,
+        con: &mut SwayConnection,
+        state: &mut State,
+        change: FullscreenChange,
+    ) -> Result<(), StateUpdateError> {
+        if change.active {
+            self.enter(con, state, change.con_id)
+        } else {
+            self.exit(con, state, change.con_id)
+        }
+    }
+
+    fn enter(
+        &mut self,
+        con: &mut SwayConnection,
+        state: &mut State,
+        fullscreen_con: i64,
+    ) -> Result<(), StateUpdateError> {
+        if self.fullscreen_con.is_some() || state.fullscreen.action == FullscreenAction::Ignore {
+            return Ok(());
+        }
+
+        // the fullscreen window usually has the focus, so the focused floating window won't do
+        let target = match find_placed_node(con, state)? {
+            Some(node) => node,
+            None => find_target_node(con)?,
+        };
+        if target.id == fullscreen_con
+            || con.find_output_for(target.id)? != con.find_output_for(fullscreen_con)?
+        {
+            return Ok(());
+        }
+
+        self.fullscreen_con = Some(fullscreen_con);
+        self.target = Some(target.id);
+        self.saved = Some(state.clone());
+
+        match state.fullscreen.action {
+            FullscreenAction::Hide => {
+                con.move_node_to_scratchpad(target.id)?;
+                self.hidden = Some(target.id);
+            }
+            _ => {
+                let reacted = state.fullscreen.apply(state);
+                *state = move_window(con, target, reacted, StateUpdate::default())?;
+            }
+        }
+        self.reacted = Some(state.clone());
+
+        Ok(())
+    }
+
+    fn exit(
+        &mut self,
+        con: &mut SwayConnection,
+        state: &mut State,
+        fullscreen_con: i64,
+    ) -> Result<(), StateUpdateError> {
+        if self.fullscreen_con != Some(fullscreen_con) {
+            return Ok(());
+        }
+        self.fullscreen_con = None;
+        let target = self.target.take();
+
+        let (Some(saved), Some(reacted)) = (self.saved.take(), self.reacted.take()) else {
+            return Ok(());
+        };
+        let restored = restore(state, &saved, &reacted);
+
+        *state = match (self.hidden.take(), target) {
+            (Some(node_id), _) => show_from_scratchpad(con, node_id, restored)?,
+            (None, Some(node_id)) => match con.find_node(node_id)? {
+                Some(node) => move_window(con, node, restored, StateUpdate::default())?,
+                // the window was closed in the meantime, so only the state is left to restore
+                None => restored,
+            },
+            (None, None) => restored,
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::daemon::unit::AbsolutePixels;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_fullscreen_options() {
+        let state = State {
+            width: Some(AbsolutePixels(600).into()),
+            height: Some(AbsolutePixels(400).into()),
+            ..Default::default()
+        };
+
+        let mut options = FullscreenOptions {
+            action: FullscreenAction::Shrink,
+            ..Default::default()
+        };
+        let shrunk = options.apply(&state);
+        assert!(matches!(
+            shrunk.width,
+            Some(AbsoluteUnit::Percentage(AbsolutePercentage(15.0)))
+        ));
+        assert!(shrunk.height.is_none());
+        assert!(matches!(
+            shrunk.position,
+            Position(Vertical::Bottom, Horizontal::Right)
+        ));
+
+        options.action = FullscreenAction::Move;
+        let moved = options.apply(&state);
+        assert!(matches!(
+            moved.width,
+            Some(AbsoluteUnit::Pixels(AbsolutePixels(600)))
+        ));
+        assert!(matches!(
+            moved.position,
+            Position(Vertical::Top, Horizontal::Right)
+        ));
+    }
+
+    #[test]
+    fn test_restore_after_fullscreen() {
+        let saved = State {
+            width: Some(AbsolutePixels(600).into()),
+            height: Some(AbsolutePixels(400).into()),
+            ..Default::default()
+        };
+        let options = FullscreenOptions {
+            action: FullscreenAction::Shrink,
+            ..Default::default()
+        };
+
+        // entering and leaving fullscreen without any updates gives back the saved state
+        let reacted = options.apply(&saved);
+        let restored = restore(&reacted, &saved, &reacted);
+        assert_eq!(restored.width, saved.width);
+        assert_eq!(restored.height, saved.height);
+        assert_eq!(restored.position, saved.position);
+
+        // updates made while the other window was fullscreen are kept
+        let mut updated = reacted.clone();
+        updated.width = Some(AbsolutePixels(300).into());
+        updated.position = Position(Vertical::Top, Horizontal::Left);
+        let restored = restore(&updated, &saved, &reacted);
+        assert_eq!(restored.width, Some(AbsolutePixels(300).into()));
+        assert_eq!(restored.height, saved.height);
+        assert_eq!(restored.position, Position(Vertical::Top, Horizontal::Left));
+    }
+}
@@ -1,80 +1,159 @@
-use serde::de::DeserializeOwned;
 use std::{
-    io,
-    os::{fd::AsRawFd, unix::net::UnixListener},
+    fs::{self, Permissions},
+    io::{self, BufRead, BufReader, Read, Write},
+    os::{
+        fd::AsRawFd,
+        unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
     path::PathBuf,
-    sync::mpsc::Sender,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
     thread,
     time::Duration,
 };
 
 use crate::{
-    client::send_message,
-    daemon::{DaemonError, DaemonEvent},
+    client::{send_message, ClientError},
+    daemon::{
+        protocol::{parse_request, Hello, Response},
+        status::{update_status, SharedStatus},
+        DaemonError, DaemonEvent,
+    },
+    debug,
+    discovery::socket_dir,
+    error, info, warn,
 };
 
+/// Only the owner of the daemon may use the socket
+const SOCKET_MODE: u32 = 0o600;
+/// Nobody else needs to see which daemons are running
+const SOCKET_DIR_MODE: u32 = 0o700;
+/// Other users need to be able to connect when they are allowed in, and the peer credentials of
+/// each connection are checked instead
+const SHARED_SOCKET_MODE: u32 = 0o666;
+/// Other users can reach the socket, but still can't list the directory
+const SHARED_SOCKET_DIR_MODE: u32 = 0o711;
+
+/// How long a new client gets to send its first message, so silent clients can't hold on to one
+/// of the few connections
+const FIRST_MESSAGE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a client that has sent a message can keep the connection open between messages
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long a client gets to take our reply
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Messages are small, so a line bigger than this is a confused or hostile client
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+/// How many clients can be handled at the same time
+const MAX_CONNECTIONS: usize = 16;
+
 pub struct IpcSocket {
     fd: i32,
     path: PathBuf,
+    running: Arc<AtomicBool>,
     _thread: thread::JoinHandle<()>,
 }
 
 impl IpcSocket {
-    pub fn init<T: DeserializeOwned + Send + std::fmt::Debug + 'static>(
+    pub fn init(
         path: PathBuf,
-        tx: Sender<T>,
+        tx: Sender<DaemonEvent>,
+        status: SharedStatus,
+        access: AccessControl,
     ) -> Result<Self, io::Error> {
         let socket = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, Permissions::from_mode(access.socket_mode()))?;
         let fd = socket.as_raw_fd();
+        let running = Arc::new(AtomicBool::new(true));
+        let r = running.clone();
+
+        let handler = Arc::new(ConnectionHandler {
+            tx,
+            status,
+            access,
+            connections: AtomicUsize::new(0),
+        });
 
         let _thread = thread::spawn(move || {
-            for event in socket.incoming() {
-                match event {
-                    Ok(stream) => {
-                        let msg = serde_json::from_reader(stream)
-                            .expect("message should be serializable");
-                        eprintln!("Received message: {:?}", msg);
-
-                        tx.send(msg).expect("failed to send message");
-                    }
-                    Err(_) => {
-                        break;
+            for stream in socket.incoming() {
+                if !r.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match stream {
+                    Ok(stream) => handler.spawn(stream),
+                    // ex: running out of file descriptors, which will hopefully pass
+                    Err(e) => {
+                        warn!("Failed to accept connection: {}", e);
+                        thread::sleep(Duration::from_millis(100));
                     }
                 }
             }
 
-            eprintln!("Socket listener was closed.");
+            debug!("Socket listener was closed.");
         });
 
-        Ok(Self { fd, path, _thread })
+        Ok(Self {
+            fd,
+            path,
+            running,
+            _thread,
+        })
     }
 
     pub fn init_or_replace(
         socket_path: &PathBuf,
         tx: Sender<DaemonEvent>,
+        status: SharedStatus,
+        access: AccessControl,
     ) -> Result<Self, DaemonError> {
         match std::fs::exists(socket_path) {
             Ok(true) => {
-                eprintln!("Socket already exists, shutting down existing daemon...");
-                send_message(socket_path, DaemonEvent::Shutdown)?;
+                info!("Socket already exists, shutting down existing daemon...");
+                match send_message(socket_path, DaemonEvent::Shutdown) {
+                    // daemons from before the protocol was versioned don't reply
+                    Ok(()) | Err(ClientError::NoReply) => {}
+                    // nothing is listening, the previous daemon didn't get to clean up
+                    Err(ClientError::IoError(e))
+                        if e.kind() == io::ErrorKind::ConnectionRefused =>
+                    {
+                        warn!("Removing stale socket {}", socket_path.display());
+                        std::fs::remove_file(socket_path)?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
 
                 while let Ok(true) = std::fs::exists(socket_path) {
                     thread::sleep(Duration::from_millis(100));
                 }
             }
-            _ => eprintln!("Socket does not exist, creating it..."),
+            _ => debug!("Socket does not exist, creating it..."),
         }
 
-        let Some(_) = socket_path.parent().map(std::fs::create_dir_all) else {
-            eprintln!("No parent directory found for socket");
+        let Some(dir) = socket_path.parent() else {
+            error!("No parent directory found for socket");
             return Err(DaemonError::IoError(io::Error::new(
                 io::ErrorKind::NotFound,
                 "No parent directory found for socket",
             )));
         };
+        // only lock down directories that are ours, and not something like `/tmp`
+        if !dir.exists() || dir == socket_dir() {
+            fs::create_dir_all(dir)?;
+            fs::set_permissions(dir, Permissions::from_mode(access.dir_mode()))?;
+        }
+        if access.is_shared() && socket_path.starts_with(socket_dir()) {
+            // `XDG_RUNTIME_DIR` itself is private to its owner, so nobody else can get through it
+            warn!("Other users can't reach sockets in XDG_RUNTIME_DIR, use --socket to put it elsewhere");
+        }
 
-        let socket = Self::init(socket_path.clone(), tx)?;
-        eprintln!("Listening on {}", socket_path.display());
+        let socket = Self::init(socket_path.clone(), tx, status, access)?;
+        info!(socket = socket_path; "Listening for commands");
 
         Ok(socket)
     }
@@ -82,8 +161,219 @@ impl IpcSocket {
     pub fn shutdown(self) {}
 }
 
+/// Handles each client on its own thread, so one slow or broken client can't hold up the others
+struct ConnectionHandler {
+    tx: Sender<DaemonEvent>,
+    status: SharedStatus,
+    access: AccessControl,
+    connections: AtomicUsize,
+}
+
+impl ConnectionHandler {
+    fn spawn(self: &Arc<Self>, mut stream: UnixStream) {
+        if self.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            self.connections.fetch_sub(1, Ordering::SeqCst);
+            warn!("Too many connections, rejecting a new one");
+            reply(
+                &mut stream,
+                &Response::Error("Too many connections".to_string()),
+            );
+            return;
+        }
+
+        let handler = self.clone();
+        thread::spawn(move || {
+            if let Err(e) = handler.handle(&mut stream) {
+                warn!("Failed to handle connection: {}", e);
+            }
+            handler.connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Answer every message on the connection, until the client hangs up
+    ///
+    /// Messages are one JSON object per line, and each one gets a reply on its own line. A message
+    /// without a trailing newline is fine too, as long as the client hangs up after it.
+    fn handle(&self, stream: &mut UnixStream) -> io::Result<()> {
+        if let Err(e) = self.access.check(stream) {
+            warn!("Rejected connection: {}", e);
+            reply(stream, &Response::Error(e.to_string()));
+            return Ok(());
+        }
+
+        stream.set_read_timeout(Some(FIRST_MESSAGE_TIMEOUT))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut idle_timeout_set = false;
+
+        loop {
+            let mut message = Vec::new();
+            match (&mut reader)
+                .take(MAX_MESSAGE_SIZE + 1)
+                .read_until(b'\n', &mut message)
+            {
+                Ok(0) => return Ok(()),
+                // we can't tell where the next message would start, so give up on the connection
+                Ok(_) if message.len() as u64 > MAX_MESSAGE_SIZE => {
+                    let response = Response::Error("Message is too large".to_string());
+                    warn!(response = response; "Rejected message");
+                    reply(stream, &response);
+                    return Ok(());
+                }
+                Ok(_) => {}
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    reply(
+                        stream,
+                        &Response::Error("Timed out waiting for a message".to_string()),
+                    );
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            }
+
+            if message.trim_ascii().is_empty() {
+                continue;
+            }
+
+            let response = self.respond(&message);
+            reply(stream, &response);
+
+            // the client has shown it speaks the protocol, so it may keep the connection around
+            if !idle_timeout_set {
+                stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+                idle_timeout_set = true;
+            }
+        }
+    }
+
+    fn respond(&self, message: &[u8]) -> Response {
+        let msg = match parse_request(message) {
+            Ok(msg) => msg,
+            Err(response) => {
+                warn!(response = response; "Rejected message");
+                return response;
+            }
+        };
+        debug!(message = msg; "Received message");
+
+        match msg {
+            DaemonEvent::Hello => Response::Hello(Hello::current()),
+            // the status is shared, so the reply doesn't have to wait on the main loop
+            DaemonEvent::Status => {
+                let mut reply = None;
+                update_status(&self.status, |status| {
+                    reply = Some(Response::Status(Box::new(status.clone())));
+                });
+                reply.expect("status should have been read")
+            }
+            msg => match self.tx.send(msg) {
+                Ok(()) => Response::Ok,
+                Err(_) => Response::Error("The daemon is shutting down".to_string()),
+            },
+        }
+    }
+}
+
+/// Send a response on its own line, which the client may not be around to read anymore
+fn reply(stream: &mut UnixStream, response: &Response) {
+    let mut reply = serde_json::to_vec(response).expect("response should be serializable");
+    reply.push(b'\n');
+    if let Err(e) = stream.write_all(&reply) {
+        warn!("Failed to reply to message: {}", e);
+    }
+}
+
+/// Decides which users may send commands to the daemon
+#[derive(Debug, Clone)]
+pub struct AccessControl {
+    owner: u32,
+    allowed: Vec<u32>,
+}
+
+impl AccessControl {
+    /// Only allow the user running the daemon, and the given extra users
+    pub fn new(allowed: Vec<u32>) -> Self {
+        Self {
+            owner: unsafe { libc::getuid() },
+            allowed,
+        }
+    }
+
+    /// Whether users other than the owner may send commands
+    fn is_shared(&self) -> bool {
+        self.allowed.iter().any(|uid| *uid != self.owner)
+    }
+
+    fn socket_mode(&self) -> u32 {
+        if self.is_shared() {
+            SHARED_SOCKET_MODE
+        } else {
+            SOCKET_MODE
+        }
+    }
+
+    fn dir_mode(&self) -> u32 {
+        if self.is_shared() {
+            SHARED_SOCKET_DIR_MODE
+        } else {
+            SOCKET_DIR_MODE
+        }
+    }
+
+    fn allows(&self, uid: u32) -> bool {
+        uid == self.owner || self.allowed.contains(&uid)
+    }
+
+    fn check(&self, stream: &UnixStream) -> Result<(), io::Error> {
+        let peer = peer_credentials(stream)?;
+        if self.allows(peer.uid) {
+            return Ok(());
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "uid {} (pid {}) is not allowed to send commands",
+                peer.uid, peer.pid
+            ),
+        ))
+    }
+}
+
+/// The user and process on the other end of the stream, as the kernel reports them
+fn peer_credentials(stream: &UnixStream) -> io::Result<libc::ucred> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(credentials)
+}
+
 impl Drop for IpcSocket {
     fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // this wakes up the listener, which then sees it should stop
         unsafe {
             libc::shutdown(self.fd, libc::SHUT_RDWR);
         }
@@ -91,3 +381,150 @@ impl Drop for IpcSocket {
         let _ = std::fs::remove_file(&self.path);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::daemon::protocol::Request;
+
+    use super::*;
+
+    #[test]
+    fn test_access_control() {
+        let (ours, _theirs) = UnixStream::pair().unwrap();
+        let uid = unsafe { libc::getuid() };
+
+        let peer = peer_credentials(&ours).unwrap();
+        assert_eq!(peer.uid, uid);
+        assert_eq!(peer.pid as u32, std::process::id());
+
+        let access = AccessControl::new(vec![]);
+        assert!(access.check(&ours).is_ok());
+        assert!(!access.allows(uid + 1));
+
+        assert_eq!(access.socket_mode(), SOCKET_MODE);
+        assert_eq!(access.dir_mode(), SOCKET_DIR_MODE);
+
+        // other users have to be able to connect before their credentials can be checked
+        let access = AccessControl::new(vec![uid + 1]);
+        assert!(access.allows(uid + 1));
+        assert!(!access.allows(uid + 2));
+        assert_eq!(access.socket_mode(), SHARED_SOCKET_MODE);
+        assert_eq!(access.dir_mode(), SHARED_SOCKET_DIR_MODE);
+
+        assert_eq!(AccessControl::new(vec![uid]).socket_mode(), SOCKET_MODE);
+    }
+
+    #[test]
+    fn test_socket_permissions() {
+        let dir = std::env::temp_dir().join(format!("sway-gravity-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let uid = unsafe { libc::getuid() };
+
+        for (access, mode) in [
+            (AccessControl::new(vec![]), SOCKET_MODE),
+            (AccessControl::new(vec![uid + 1]), SHARED_SOCKET_MODE),
+        ] {
+            let path = dir.join("gravity.sock");
+            let (tx, _rx) = std::sync::mpsc::channel();
+            let socket = IpcSocket::init(path.clone(), tx, Default::default(), access).unwrap();
+
+            let permissions = fs::metadata(&path).unwrap().permissions();
+            assert_eq!(permissions.mode() & 0o777, mode);
+            drop(socket);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_persistent_connection() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handler = ConnectionHandler {
+            tx,
+            status: Default::default(),
+            access: AccessControl::new(vec![]),
+            connections: AtomicUsize::new(0),
+        };
+
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        for event in [
+            DaemonEvent::Unfloat,
+            DaemonEvent::Hello,
+            DaemonEvent::Reflow,
+        ] {
+            let mut message = serde_json::to_vec(&Request::new(event)).unwrap();
+            message.push(b'\n');
+            client.write_all(&message).unwrap();
+        }
+        client.write_all(b"\n{\n").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+
+        handler.handle(&mut server).unwrap();
+        drop(server);
+
+        let replies: Vec<Response> = BufReader::new(client)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        assert_eq!(replies.len(), 4);
+        assert!(matches!(replies[0], Response::Ok));
+        assert!(matches!(replies[1], Response::Hello(_)));
+        assert!(matches!(replies[2], Response::Ok));
+        assert!(matches!(replies[3], Response::Error(_)));
+
+        assert!(matches!(rx.try_recv(), Ok(DaemonEvent::Unfloat)));
+        assert!(matches!(rx.try_recv(), Ok(DaemonEvent::Reflow)));
+    }
+
+    #[test]
+    fn test_silent_client_times_out() {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let handler = ConnectionHandler {
+            tx,
+            status: Default::default(),
+            access: AccessControl::new(vec![]),
+            connections: AtomicUsize::new(0),
+        };
+
+        let (client, mut server) = UnixStream::pair().unwrap();
+        let started = std::time::Instant::now();
+        assert!(handler.handle(&mut server).is_err());
+        assert!(started.elapsed() < IDLE_TIMEOUT);
+        drop(client);
+    }
+
+    fn roundtrip(handler: &ConnectionHandler, message: &[u8]) -> Response {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        client.write_all(message).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+
+        handler.handle(&mut server).unwrap();
+        drop(server);
+
+        serde_json::from_reader(client).unwrap()
+    }
+
+    #[test]
+    fn test_handle_connection() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handler = ConnectionHandler {
+            tx,
+            status: Default::default(),
+            access: AccessControl::new(vec![]),
+            connections: AtomicUsize::new(0),
+        };
+
+        let message = serde_json::to_vec(&Request::new(DaemonEvent::Shutdown)).unwrap();
+        assert!(matches!(roundtrip(&handler, &message), Response::Ok));
+        assert!(matches!(rx.try_recv(), Ok(DaemonEvent::Shutdown)));
+
+        // bad messages get an error back, instead of taking the listener down
+        assert!(matches!(roundtrip(&handler, b"{"), Response::Error(_)));
+        let huge = vec![b' '; MAX_MESSAGE_SIZE as usize + 1];
+        assert!(matches!(roundtrip(&handler, &huge), Response::Error(_)));
+        assert!(rx.try_recv().is_err());
+
+        drop(rx);
+        assert!(matches!(roundtrip(&handler, &message), Response::Error(_)));
+    }
+}
@@ -1,22 +1,50 @@
-use std::{error::Error, fmt::Display, io, path::PathBuf, sync::mpsc::channel};
+use std::{
+    error::Error,
+    fmt::Display,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::channel,
+        Arc,
+    },
+};
 
 use crate::{
     cli::Args,
     client::ClientError,
     daemon::{
-        ipc::IpcSocket,
-        state::{PositionUpdate, StateUpdate, StateUpdateError},
+        batch::{run_batch, BatchStep},
+        float::{FloatRequest, TilingSpots},
+        fullscreen::{FullscreenChange, FullscreenTracker},
+        ipc::{AccessControl, IpcSocket},
+        persist::save_state,
+        reconnect::{connect_with_backoff, is_connection_error, sway_socket_gone},
+        scratchpad::{Scratchpad, ScratchpadAction},
+        slot::{SlotUpdate, Slots},
+        state::{Layout, PositionUpdate, StateUpdate, StateUpdateError},
+        status::{update_status, DaemonStatus},
         sway::SwaySubscription,
     },
-    find_target_node, move_window,
+    debug, error, find_placed_node, info, move_window, place_strip, place_windows,
     sway::SwayConnection,
     State,
 };
 use serde::{Deserialize, Serialize};
 use swayipc::Connection;
 
+pub mod batch;
+pub mod expression;
+pub mod float;
+pub mod fullscreen;
 pub mod ipc;
+pub mod persist;
+pub mod protocol;
+pub mod reconnect;
+pub mod scratchpad;
+pub mod slot;
 pub mod state;
+pub mod status;
 pub mod sway;
 pub mod unit;
 
@@ -24,42 +52,122 @@ pub fn run_daemon(
     socket_path: PathBuf,
     initial_state: State,
     sway_delay: u64,
+    access: AccessControl,
+    state_file: Option<PathBuf>,
 ) -> Result<(), DaemonError> {
     let mut state = initial_state;
     let mut con = SwayConnection::new()?;
+    let mut fullscreen = FullscreenTracker::default();
+    let mut scratchpad = Scratchpad::default();
+    let mut tiling_spots = TilingSpots::default();
+    let mut slots = Slots::default();
+    let status = DaemonStatus::shared();
 
     let (tx, rx) = channel::<DaemonEvent>();
     let sway_tx = tx.clone();
     let ctrlc_tx = tx.clone();
 
-    let socket = IpcSocket::init_or_replace(&socket_path, tx)?;
-    let sway_sub = SwaySubscription::init(Connection::new, sway_tx, sway_delay)?;
+    let socket = IpcSocket::init_or_replace(&socket_path, tx, status.clone(), access)?;
+    let sway_sub = SwaySubscription::init(Connection::new, sway_tx, sway_delay, status.clone())?;
 
+    // the daemon can't see the shutdown event while it's waiting for sway to come back
+    let stopping = Arc::new(AtomicBool::new(false));
+    let ctrlc_stopping = stopping.clone();
+    // with the `termination` feature, this also catches SIGTERM (ex: from `pkill` or systemd)
     ctrlc::set_handler(move || {
+        ctrlc_stopping.store(true, Ordering::SeqCst);
         ctrlc_tx
             .send(DaemonEvent::Shutdown)
             .expect("Failed to send shutdown event");
     })
-    .expect("Error setting Ctrl-C handler");
+    .expect("Error setting the signal handler");
 
     for event in rx.iter() {
-        match event {
+        debug!(event = event.kind(); "Handling event");
+        let (action, result) = match event {
             DaemonEvent::Shutdown => {
-                eprintln!("Shutdown requested.");
+                info!("Shutdown requested.");
                 break;
             }
-            DaemonEvent::Update(update) => {
-                let window = find_target_node(&mut con)?;
+            // answered by the socket listener directly
+            DaemonEvent::Status | DaemonEvent::Hello => continue,
+            DaemonEvent::Update(update) => (
+                "move window".to_string(),
+                place_windows(&mut con, state.clone(), update).map(|updated| {
+                    state = updated;
+                    debug!(state = state; "Window moved successfully");
+                }),
+            ),
+            DaemonEvent::Slot(slot) => (
+                format!("move the window in slot {}", slot.name),
+                slots.handle(&mut con, &state, slot),
+            ),
+            DaemonEvent::Batch(steps) => (
+                format!("run a batch of {} steps", steps.len()),
+                run_batch(&mut con, &mut state, &mut slots, steps),
+            ),
+            DaemonEvent::Fullscreen(change) => {
+                let closed = change.closed;
+                let result = fullscreen.handle(&mut con, &mut state, change);
+                // a closed window also leaves a hole in the strip, unlike one leaving fullscreen
+                let reflowed = if closed {
+                    reflow(&mut con, &mut state)
+                } else {
+                    Ok(())
+                };
 
-                match move_window(&mut con, window, state.clone(), update) {
-                    Ok(updated) => {
-                        state = updated;
-                        eprintln!("Window moved successfully: {:?}", state);
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to move window: {}", e);
+                (
+                    "react to fullscreen change".to_string(),
+                    result.and(reflowed),
+                )
+            }
+            DaemonEvent::Reflow => ("reflow the strip".to_string(), reflow(&mut con, &mut state)),
+            DaemonEvent::Reapply => (
+                "put the windows back in place".to_string(),
+                reapply(&mut con, &mut state, &mut slots),
+            ),
+            DaemonEvent::Scratchpad(action) => (
+                format!("{:?} the scratchpad", action),
+                scratchpad.handle(&mut con, &mut state, action),
+            ),
+            DaemonEvent::Float(request) => (
+                "float window".to_string(),
+                tiling_spots.float(&mut con, &mut state, request),
+            ),
+            DaemonEvent::Unfloat => ("unfloat window".to_string(), tiling_spots.unfloat(&mut con)),
+        };
+
+        match result {
+            Ok(()) => update_status(&status, |status| {
+                status.state = Some(state.clone().into());
+            }),
+            Err(e) => {
+                error!("Failed to {}: {}", action, e);
+                update_status(&status, |status| {
+                    status.last_error = Some(format!("Failed to {}: {}", action, e));
+                });
+
+                if let StateUpdateError::SwayIPC(err) = &e {
+                    if is_connection_error(err) {
+                        update_status(&status, |status| status.sway_connected = false);
+                        let stopped = || stopping.load(Ordering::SeqCst);
+                        con = match connect_with_backoff("daemon", SwayConnection::new, stopped) {
+                            Ok(con) => con,
+                            // sway is gone or the daemon is shutting down, either way it's over
+                            Err(_) => {
+                                if sway_socket_gone() {
+                                    info!("Sway's socket is gone, shutting down.");
+                                }
+                                break;
+                            }
+                        };
+                        update_status(&status, |status| {
+                            status.sway_connected = true;
+                            status.reconnects += 1;
+                        });
+                        info!("Reconnected to sway.");
                     }
-                };
+                }
             }
         }
     }
@@ -67,19 +175,139 @@ pub fn run_daemon(
     socket.shutdown();
     sway_sub.shutdown();
 
+    if let Some(path) = state_file {
+        match save_state(&path, &state) {
+            Ok(()) => info!("Saved the state to {}.", path.display()),
+            Err(e) => error!("Failed to save the state to {}: {}", path.display(), e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-arrange the strip after its windows changed
+fn reflow(con: &mut SwayConnection, state: &mut State) -> Result<(), StateUpdateError> {
+    if state.layout != Layout::Strip {
+        return Ok(());
+    }
+
+    *state = place_strip(con, state.clone(), StateUpdate::default())?;
+
+    Ok(())
+}
+
+/// Put every window the daemon controls back in its place, after the outputs or the config changed
+///
+/// Percentages are measured against the outputs as they are now, so they are resized as well.
+fn reapply(
+    con: &mut SwayConnection,
+    state: &mut State,
+    slots: &mut Slots,
+) -> Result<(), StateUpdateError> {
+    let placed = match state.layout {
+        Layout::Single => reapply_placed(con, state),
+        Layout::Strip => reflow(con, state).or_else(|e| match e {
+            // there's no floating window to put back
+            StateUpdateError::NoApplicableNode => Ok(()),
+            e => Err(e),
+        }),
+    };
+
+    // the slots are put back even if the usual window couldn't be
+    let reapplied = slots.reapply(con);
+    placed.and(reapplied)
+}
+
+/// Put the window the daemon placed last back in its place
+///
+/// The focus could be anywhere by now, so the window is found by its id. There's nothing to do
+/// once it's closed or hidden in the scratchpad.
+fn reapply_placed(con: &mut SwayConnection, state: &mut State) -> Result<(), StateUpdateError> {
+    let Some(node) = find_placed_node(con, state)? else {
+        return Ok(());
+    };
+    *state = move_window(con, node, state.clone(), StateUpdate::default())?;
+
     Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DaemonEvent {
     Shutdown,
+    /// Ask the daemon how it's doing, which it replies to with a `DaemonStatus`
+    Status,
+    /// Ask the daemon which protocol version and events it supports
+    Hello,
     Update(StateUpdate),
+    Slot(SlotUpdate),
+    /// Several updates applied at once, which either all succeed or leave the state untouched
+    Batch(Vec<BatchStep>),
+    Fullscreen(FullscreenChange),
+    Scratchpad(ScratchpadAction),
+    Float(FloatRequest),
+    Unfloat,
+    /// A window was added to or removed from the floating windows on a workspace
+    Reflow,
+    /// The outputs changed or sway reloaded, so every window has to be placed again
+    Reapply,
+}
+
+impl DaemonEvent {
+    /// Every kind of event, which the daemon advertises as its capabilities
+    pub const KINDS: &[&str] = &[
+        "shutdown",
+        "status",
+        "hello",
+        "update",
+        "slot",
+        "batch",
+        "fullscreen",
+        "scratchpad",
+        "float",
+        "unfloat",
+        "reflow",
+        "reapply",
+    ];
+
+    /// A short name for the kind of event, for logging
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DaemonEvent::Shutdown => "shutdown",
+            DaemonEvent::Status => "status",
+            DaemonEvent::Hello => "hello",
+            DaemonEvent::Update(_) => "update",
+            DaemonEvent::Slot(_) => "slot",
+            DaemonEvent::Batch(_) => "batch",
+            DaemonEvent::Fullscreen(_) => "fullscreen",
+            DaemonEvent::Scratchpad(_) => "scratchpad",
+            DaemonEvent::Float(_) => "float",
+            DaemonEvent::Unfloat => "unfloat",
+            DaemonEvent::Reflow => "reflow",
+            DaemonEvent::Reapply => "reapply",
+        }
+    }
 }
 
 impl From<Args> for DaemonEvent {
     fn from(args: Args) -> Self {
         if args.shutdown {
             Self::Shutdown
+        } else if args.status {
+            Self::Status
+        } else if let Some(action) = args.scratchpad {
+            Self::Scratchpad(action)
+        } else if args.unfloat {
+            Self::Unfloat
+        } else if args.float || args.sticky {
+            Self::Float(FloatRequest {
+                sticky: args.sticky,
+                update: StateUpdate::from(args),
+            })
+        } else if let Some(name) = args.target.slot.clone() {
+            Self::Slot(SlotUpdate {
+                name,
+                update: StateUpdate::from(args),
+            })
         } else {
             Self::Update(StateUpdate::from(args))
         }
@@ -89,11 +317,23 @@ impl From<Args> for DaemonEvent {
 impl From<Args> for StateUpdate {
     fn from(args: Args) -> Self {
         Self {
-            position: PositionUpdate(args.vertical, args.horizontal),
-            padding: args.padding,
-            width: args.width,
-            height: args.height,
-            natural: args.natural,
+            position: PositionUpdate(args.placement.vertical, args.placement.horizontal),
+            padding: args.placement.padding,
+            width: args.size.width,
+            height: args.size.height,
+            natural: args.size.natural,
+            aspect: args.size.aspect,
+            layout: args.placement.layout,
+            offset_x: args.placement.offset_x,
+            offset_y: args.placement.offset_y,
+            nudge_x: args.placement.nudge_x,
+            nudge_y: args.placement.nudge_y,
+            on_fullscreen: args.fullscreen.on_fullscreen,
+            fullscreen_width: args.fullscreen.fullscreen_width,
+            fullscreen_position: PositionUpdate(
+                args.fullscreen.fullscreen_vertical,
+                args.fullscreen.fullscreen_horizontal,
+            ),
         }
     }
 }
@@ -133,6 +373,7 @@ impl From<ClientError> for DaemonError {
         match value {
             ClientError::IoError(err) => Self::IoError(err),
             ClientError::InvalidMessage(err) => Self::InvalidMessage(err),
+            other => Self::IoError(io::Error::other(other.to_string())),
         }
     }
 }
@@ -163,6 +404,6 @@ impl From<swayipc::Error> for DaemonError {
 
 impl From<std::sync::mpsc::SendError<DaemonEvent>> for DaemonError {
     fn from(value: std::sync::mpsc::SendError<DaemonEvent>) -> Self {
-        Self::IoError(io::Error::new(io::ErrorKind::Other, value))
+        Self::IoError(io::Error::other(value))
     }
 }
@@ -0,0 +1,60 @@
+use std::{fs, io, path::Path};
+
+use crate::daemon::{
+    state::{State, StateUpdate},
+    DaemonError,
+};
+
+/// Write the state to the file, so a `--oneshot` client can pick up where the daemon left off
+///
+/// The state is saved in the same form as the daemon reports it in its status.
+pub fn save_state(path: &Path, state: &State) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(&StateUpdate::from(state.clone()))?;
+
+    // readers never see a half written file, since the rename replaces it at once
+    let partial = path.with_extension("partial");
+    fs::write(&partial, json)?;
+    fs::rename(partial, path)
+}
+
+/// Read the state a daemon saved to the file
+pub fn load_state(path: &Path) -> Result<State, DaemonError> {
+    let saved: StateUpdate = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    State::from_saved(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::daemon::{
+        state::{Horizontal, Position, Vertical},
+        unit::{AbsolutePixels, RelativePercentage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_save_and_load_state() {
+        let path = env::temp_dir().join(format!("sway-gravity-state-{}.json", std::process::id()));
+        let state = State {
+            position: Position(Vertical::Top, Horizontal::Left),
+            width: Some(AbsolutePixels(600).into()),
+            offset_x: Some(RelativePercentage(30.0).into()),
+            nudge_x: 20,
+            nudge_y: -10,
+            ..Default::default()
+        };
+
+        save_state(&path, &state).unwrap();
+        let loaded = load_state(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.position, state.position);
+        assert_eq!(loaded.width, state.width);
+        assert_eq!(loaded.height, None);
+        assert_eq!(loaded.offset_x, state.offset_x);
+        assert_eq!((loaded.nudge_x, loaded.nudge_y), (20, -10));
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::{status::DaemonStatus, DaemonEvent};
+
+/// The version of the messages sent between the client and the daemon
+///
+/// This only needs to change when existing messages change shape. New events are detected through
+/// the capabilities in the handshake instead.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Features of the connection itself, which are advertised next to the kinds of events
+///
+/// - `json-lines`: a connection can carry many messages, one per line
+pub const FEATURES: &[&str] = &["json-lines"];
+
+/// What a client sends to the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    pub event: DaemonEvent,
+}
+
+impl Request {
+    pub fn new(event: DaemonEvent) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            event,
+        }
+    }
+}
+
+/// A request whose event hasn't been decoded yet, so the version can be checked first
+#[derive(Debug, Deserialize)]
+struct RawRequest {
+    version: u32,
+    event: serde_json::Value,
+}
+
+/// What the daemon replies to every request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Response {
+    Ok,
+    Hello(Hello),
+    Status(Box<DaemonStatus>),
+    /// The request was understood, but couldn't be handled
+    Error(String),
+    /// The request was sent with a version of the protocol the daemon doesn't speak
+    UnsupportedVersion {
+        supported: u32,
+    },
+}
+
+/// The reply to the handshake, describing what the daemon understands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u32,
+    pub daemon_version: String,
+    /// The kinds of events the daemon can handle, and the features it supports
+    pub capabilities: Vec<String>,
+}
+
+impl Hello {
+    pub fn current() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: DaemonEvent::KINDS
+                .iter()
+                .chain(FEATURES)
+                .map(|capability| capability.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Decode a request, or the response to reject it with
+pub fn parse_request(message: &[u8]) -> Result<DaemonEvent, Response> {
+    let request: RawRequest = serde_json::from_slice(message)
+        .map_err(|e| Response::Error(format!("Invalid message: {}", e)))?;
+
+    if request.version != PROTOCOL_VERSION {
+        return Err(Response::UnsupportedVersion {
+            supported: PROTOCOL_VERSION,
+        });
+    }
+
+    serde_json::from_value(request.event)
+        .map_err(|e| Response::Error(format!("Unsupported event: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let message = serde_json::to_vec(&Request::new(DaemonEvent::Shutdown)).unwrap();
+        assert!(matches!(parse_request(&message), Ok(DaemonEvent::Shutdown)));
+
+        let message = br#"{"version": 99, "event": "Shutdown"}"#;
+        assert!(matches!(
+            parse_request(message),
+            Err(Response::UnsupportedVersion {
+                supported: PROTOCOL_VERSION
+            })
+        ));
+
+        // slot names end up in sway commands, so bad ones are rejected before they get that far
+        let message = br#"{"version": 1, "event": {"Slot": {"name": "x, exec foo", "update": {"position": [null, null]}}}}"#;
+        assert!(matches!(parse_request(message), Err(Response::Error(_))));
+
+        let message = br#"{"version": 1, "event": "Teleport"}"#;
+        assert!(matches!(parse_request(message), Err(Response::Error(_))));
+
+        // messages from before the protocol was versioned
+        assert!(matches!(
+            parse_request(br#""Shutdown""#),
+            Err(Response::Error(_))
+        ));
+        assert!(matches!(parse_request(b"{"), Err(Response::Error(_))));
+    }
+}
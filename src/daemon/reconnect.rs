@@ -0,0 +1,115 @@
+use std::{env, path::Path, thread, time::Duration};
+
+use swayipc::Fallible;
+
+use crate::warn;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Exponentially growing delays between reconnection attempts
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            next: INITIAL_BACKOFF,
+        }
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+
+        Some(delay)
+    }
+}
+
+/// Keep trying to connect to sway, waiting a little longer after each failure
+///
+/// There is no limit on the attempts: as long as sway's socket exists, sway may only be stalled
+/// (ex: while outputs are reconfigured), and a daemon that gave up on it would be left without
+/// events or without a connection. The attempts end once the socket is gone, or once `stop` says
+/// the daemon is shutting down anyway, and the daemon then exits cleanly with the error.
+pub fn connect_with_backoff<T>(
+    name: &str,
+    mut connect: impl FnMut() -> Fallible<T>,
+    stop: impl Fn() -> bool,
+) -> Fallible<T> {
+    let mut backoff = Backoff::default();
+
+    loop {
+        match connect() {
+            Ok(con) => return Ok(con),
+            // there's no point in waiting for a sway that has exited
+            Err(e) if sway_socket_gone() || stop() => return Err(e),
+            Err(e) => {
+                let delay = backoff.next().unwrap_or(MAX_BACKOFF);
+                warn!(
+                    "Failed to connect the {} to sway, retrying in {:?}: {}",
+                    name, delay, e
+                );
+                thread::sleep(delay);
+            }
+        }
+    }
+}
+
+/// Whether the socket of the sway session we were started in no longer exists
+pub fn sway_socket_gone() -> bool {
+    env::var_os("SWAYSOCK").is_some_and(|path| !Path::new(&path).exists())
+}
+
+/// Whether the error means the connection itself is broken, as opposed to a rejected command
+pub fn is_connection_error(err: &swayipc::Error) -> bool {
+    matches!(
+        err,
+        swayipc::Error::Io(_) | swayipc::Error::InvalidMagic(_) | swayipc::Error::SocketNotFound
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_with_backoff() {
+        let mut attempts = 0;
+        let connected = connect_with_backoff(
+            "test",
+            || {
+                attempts += 1;
+                if attempts < 3 {
+                    Err(swayipc::Error::SocketNotFound)
+                } else {
+                    Ok(attempts)
+                }
+            },
+            || false,
+        );
+        assert_eq!(connected.unwrap(), 3);
+
+        // a daemon that is shutting down stops right away
+        let stopped: Fallible<()> =
+            connect_with_backoff("test", || Err(swayipc::Error::SocketNotFound), || true);
+        assert!(stopped.is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let delays: Vec<_> = Backoff::default().take(8).collect();
+
+        assert_eq!(delays[0], Duration::from_millis(100));
+        assert_eq!(delays[1], Duration::from_millis(200));
+        assert_eq!(delays[5], Duration::from_millis(3200));
+        assert_eq!(delays[6], MAX_BACKOFF);
+        assert_eq!(delays[7], MAX_BACKOFF);
+    }
+}
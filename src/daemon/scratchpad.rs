@@ -0,0 +1,74 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compute_placement,
+    daemon::state::{State, StateUpdate, StateUpdateError},
+    find_target_node,
+    sway::SwayConnection,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScratchpadAction {
+    /// Hide the window if it is visible, otherwise show it again
+    Toggle,
+    /// Show the hidden window again
+    Show,
+    /// Hide the window in the scratchpad
+    Hide,
+}
+
+/// Remembers which window we sent to the scratchpad, so it can be shown again in its place
+#[derive(Debug, Default)]
+pub struct Scratchpad {
+    hidden: Option<i64>,
+}
+
+impl Scratchpad {
+    pub fn handle(
+        &mut self,
+        con: &mut SwayConnection,
+        state: &mut State,
+        action: ScratchpadAction,
+    ) -> Result<(), StateUpdateError> {
+        // the window may have been shown by something other than us in the meantime
+        let hidden = match self.hidden {
+            Some(node_id) if con.is_node_in_scratchpad(node_id)? => Some(node_id),
+            _ => None,
+        };
+
+        match (action, hidden) {
+            (ScratchpadAction::Toggle | ScratchpadAction::Show, Some(node_id)) => {
+                *state = show_from_scratchpad(con, node_id, state.clone())?;
+                self.hidden = None;
+            }
+            (ScratchpadAction::Toggle | ScratchpadAction::Hide, None) => {
+                let target = find_target_node(con)?;
+                con.move_node_to_scratchpad(target.id)?;
+                self.hidden = Some(target.id);
+            }
+            (ScratchpadAction::Show, None) => return Err(StateUpdateError::NoApplicableNode),
+            (ScratchpadAction::Hide, Some(_)) => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Show a window from the scratchpad, placed according to the given state
+pub fn show_from_scratchpad(
+    con: &mut SwayConnection,
+    node_id: i64,
+    state: State,
+) -> Result<State, StateUpdateError> {
+    let node = con
+        .find_node(node_id)?
+        .ok_or(StateUpdateError::NoApplicableNode)?;
+    let placement = compute_placement(con, &node, state, StateUpdate::default())?;
+
+    // chaining the commands means the window never flashes in the center of the workspace
+    con.run_commands(&placement.command(&["scratchpad show".to_string()]))?;
+
+    Ok(placement.state)
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use swayipc::Node;
+
+use crate::{
+    daemon::state::{State, StateUpdate, StateUpdateError},
+    find_target_node, move_window,
+    sway::SwayConnection,
+    warn,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotUpdate {
+    #[serde(deserialize_with = "deserialize_slot_name")]
+    pub name: String,
+    pub update: StateUpdate,
+}
+
+/// Named slots, each holding on to one window (via a sway mark) and its own state
+#[derive(Debug, Default)]
+pub struct Slots {
+    states: HashMap<String, State>,
+}
+
+impl Slots {
+    /// Apply the update to the window in the slot
+    ///
+    /// If no window holds the slot's mark (the slot is new, its window was closed, or the mark was
+    /// cleared), the targeted window is claimed for the slot first.
+    pub fn handle(
+        &mut self,
+        con: &mut SwayConnection,
+        initial: &State,
+        slot: SlotUpdate,
+    ) -> Result<(), StateUpdateError> {
+        let (target, claim) = self.target(con, &slot.name)?;
+        if let Some(mark) = claim {
+            con.mark_node(target.id, &mark)?;
+        }
+
+        let state = move_window(con, target, self.state(&slot.name, initial), slot.update)?;
+        self.set_state(slot.name, state);
+
+        Ok(())
+    }
+
+    /// Put the window of every slot back in its place
+    ///
+    /// One slot failing doesn't keep the others from being put back, the first error is returned
+    /// once every slot had its turn.
+    pub fn reapply(&mut self, con: &mut SwayConnection) -> Result<(), StateUpdateError> {
+        each_slot(&mut self.states, |name, state| {
+            // the slot's window was closed, so it has nothing to put back
+            let Some(node) = con.find_node_with_mark(&slot_mark(name))? else {
+                return Ok(());
+            };
+            // a hidden window stays hidden until it's shown again
+            if con.is_node_in_scratchpad(node.id)? {
+                return Ok(());
+            }
+            *state = move_window(con, node, state.clone(), StateUpdate::default())?;
+
+            Ok(())
+        })
+    }
+
+    /// The window in the slot, and the mark to claim it with when no window holds the slot yet
+    pub fn target(
+        &self,
+        con: &mut SwayConnection,
+        name: &str,
+    ) -> Result<(Node, Option<String>), StateUpdateError> {
+        let mark = slot_mark(name);
+        match con.find_node_with_mark(&mark)? {
+            Some(node) => Ok((node, None)),
+            None => Ok((find_target_node(con)?, Some(mark))),
+        }
+    }
+
+    /// The state of the slot, which starts out as the initial state
+    pub fn state(&self, name: &str, initial: &State) -> State {
+        self.states
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| initial.clone())
+    }
+
+    pub fn set_state(&mut self, name: String, state: State) {
+        self.states.insert(name, state);
+    }
+}
+
+/// Run the step for every slot, carrying on past the slots it fails for
+fn each_slot<F>(states: &mut HashMap<String, State>, mut step: F) -> Result<(), StateUpdateError>
+where
+    F: FnMut(&str, &mut State) -> Result<(), StateUpdateError>,
+{
+    let mut first_error = None;
+    for (name, state) in states.iter_mut() {
+        if let Err(e) = step(name, state) {
+            warn!(slot = name; "Failed to put the window back: {}", e);
+            first_error.get_or_insert(e);
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+fn slot_mark(name: &str) -> String {
+    format!("_gravity_{}", name)
+}
+
+/// Slot names end up in sway commands, so only allow characters that don't need quoting
+pub fn parse_slot_name(name: &str) -> Result<String, String> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(name.to_string())
+    } else {
+        Err("slot names may only contain letters, digits, `-` and `_`".to_string())
+    }
+}
+
+/// Slot names from the socket skip the checks of the command line, so they are checked again here
+fn deserialize_slot_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    parse_slot_name(&name).map_err(serde::de::Error::custom)
+}
+
+pub fn deserialize_optional_slot_name<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| parse_slot_name(&name).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_slot_name() {
+        assert_eq!(parse_slot_name("pip"), Ok("pip".to_string()));
+        assert_eq!(
+            parse_slot_name("video-call_2"),
+            Ok("video-call_2".to_string())
+        );
+        assert!(parse_slot_name("").is_err());
+        assert!(parse_slot_name("pip chat").is_err());
+        assert!(parse_slot_name("pip,kill").is_err());
+    }
+
+    #[test]
+    fn test_each_slot_carries_on_after_failures() {
+        let mut states: HashMap<String, State> = ["chat", "pip", "video"]
+            .into_iter()
+            .map(|name| (name.to_string(), State::default()))
+            .collect();
+
+        let result = each_slot(&mut states, |name, state| {
+            if name == "pip" {
+                return Err(StateUpdateError::NoApplicableNode);
+            }
+            state.nudge_x = 10;
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(StateUpdateError::NoApplicableNode)));
+        assert_eq!(states["chat"].nudge_x, 10);
+        assert_eq!(states["pip"].nudge_x, 0);
+        assert_eq!(states["video"].nudge_x, 10);
+    }
+
+    #[test]
+    fn test_deserialize_slot_update() {
+        let update: SlotUpdate =
+            serde_json::from_str(r#"{"name": "pip", "update": {"position": [null, null]}}"#)
+                .unwrap();
+        assert_eq!(update.name, "pip");
+
+        let injected = r#"{"name": "x, exec foo", "update": {"position": [null, null]}}"#;
+        assert!(serde_json::from_str::<SlotUpdate>(injected).is_err());
+    }
+}
@@ -7,25 +7,41 @@ use swayipc::Error as SwayIPCError;
 use crate::{
     cli::Args,
     daemon::{
-        unit::{AbsolutePixels, AbsoluteUnit, Unit},
+        fullscreen::{FullscreenAction, FullscreenOptions},
+        unit::{AbsolutePixels, AbsoluteUnit, Aspect, RelativePixels, RelativeUnit, Unit},
         DaemonError,
     },
-    sway::Window,
+    sway::{CommandError, Window},
 };
 
 pub struct InitialStateOptions {
     pub position: PositionUpdate,
-    pub padding: Option<u32>,
+    pub padding: Option<AbsoluteUnit>,
     pub width: Option<AbsoluteUnit>,
     pub height: Option<AbsoluteUnit>,
     pub natural: Option<bool>,
+    pub aspect: Option<Aspect>,
+    pub layout: Option<Layout>,
+    pub offset_x: Option<RelativeUnit>,
+    pub offset_y: Option<RelativeUnit>,
+    pub on_fullscreen: Option<FullscreenAction>,
+    pub fullscreen_width: Option<AbsoluteUnit>,
+    pub fullscreen_position: PositionUpdate,
 }
 
 impl TryFrom<Args> for InitialStateOptions {
     type Error = DaemonError;
 
     fn try_from(args: Args) -> Result<Self, Self::Error> {
-        let width = match args.width {
+        StateUpdate::from(args).try_into()
+    }
+}
+
+impl TryFrom<StateUpdate> for InitialStateOptions {
+    type Error = DaemonError;
+
+    fn try_from(update: StateUpdate) -> Result<Self, Self::Error> {
+        let width = match update.width {
             Some(Unit::Absolute(width)) => Some(width),
             Some(Unit::Relative(_)) => {
                 return Err(DaemonError::InvalidInitialState(
@@ -35,7 +51,7 @@ impl TryFrom<Args> for InitialStateOptions {
             None => None,
         };
 
-        let height = match args.height {
+        let height = match update.height {
             Some(Unit::Absolute(height)) => Some(height),
             Some(Unit::Relative(_)) => {
                 return Err(DaemonError::InvalidInitialState(
@@ -46,11 +62,18 @@ impl TryFrom<Args> for InitialStateOptions {
         };
 
         Ok(Self {
-            position: PositionUpdate(args.vertical, args.horizontal),
-            padding: args.padding,
+            position: update.position,
+            padding: update.padding,
             width,
             height,
-            natural: args.natural,
+            natural: update.natural,
+            aspect: update.aspect,
+            layout: update.layout,
+            offset_x: update.offset_x,
+            offset_y: update.offset_y,
+            on_fullscreen: update.on_fullscreen,
+            fullscreen_width: update.fullscreen_width,
+            fullscreen_position: update.fullscreen_position,
         })
     }
 }
@@ -58,14 +81,41 @@ impl TryFrom<Args> for InitialStateOptions {
 #[derive(Debug, Default, Clone)]
 pub struct State {
     pub position: Position,
-    pub padding: u32,
+    pub padding: AbsoluteUnit,
     pub width: Option<AbsoluteUnit>,
     pub height: Option<AbsoluteUnit>,
     pub natural: bool,
+    pub aspect: Aspect,
+    pub layout: Layout,
+    /// How far the window is moved from its anchor, with positive values going right and down
+    pub offset_x: Option<RelativeUnit>,
+    pub offset_y: Option<RelativeUnit>,
+    /// The nudges since the window was last anchored, in pixels
+    pub nudge_x: i32,
+    pub nudge_y: i32,
+    pub fullscreen: FullscreenOptions,
+    /// The window the state was last applied to
+    pub node_id: Option<i64>,
 }
 
 impl State {
     pub fn update(&mut self, update: StateUpdate, context: &Window) {
+        // nudges are relative to the anchor, so they start over once the window is anchored again
+        let anchored = update.position.0.is_some()
+            || update.position.1.is_some()
+            || update.offset_x.is_some()
+            || update.offset_y.is_some();
+        if anchored {
+            self.nudge_x = 0;
+            self.nudge_y = 0;
+        }
+        if let Some(offset_x) = update.offset_x {
+            self.offset_x = Some(offset_x);
+        }
+        if let Some(offset_y) = update.offset_y {
+            self.offset_y = Some(offset_y);
+        }
+
         self.position.update(update.position);
         if let Some(padding) = update.padding {
             self.padding = padding;
@@ -73,42 +123,91 @@ impl State {
         if let Some(natural) = update.natural {
             self.natural = natural;
         }
+        if let Some(aspect) = update.aspect {
+            self.aspect = aspect;
+        }
+        if let Some(layout) = update.layout {
+            self.layout = layout;
+        }
+        self.fullscreen.update(
+            update.on_fullscreen,
+            update.fullscreen_width,
+            update.fullscreen_position,
+        );
 
         let default_width = AbsolutePixels::from(context.dimensions.width as u32).into();
         let default_height = AbsolutePixels::from(context.dimensions.height as u32).into();
 
+        // physical lengths can only be adjusted once we know the density of the output
+        let current_width = self
+            .width
+            .as_ref()
+            .map(|w| w.resolve(&context.horizontal_density))
+            .unwrap_or(default_width);
+        let current_height = self
+            .height
+            .as_ref()
+            .map(|h| h.resolve(&context.vertical_density))
+            .unwrap_or(default_height);
+
         let parent_width: AbsolutePixels = (context.working_area.width as u32).into();
         let parent_height: AbsolutePixels = (context.working_area.height as u32).into();
 
         // If only one dimension is provided, we probably want to set the other to None
         match (update.width, update.height) {
             (Some(width), Some(height)) => {
-                self.width = Some(
-                    width.to_absolute(self.width.clone().unwrap_or(default_width), parent_width),
-                );
-                self.height = Some(
-                    height
-                        .to_absolute(self.height.clone().unwrap_or(default_height), parent_height),
-                );
+                self.width = Some(width.to_absolute(current_width.clone(), parent_width));
+                self.height = Some(height.to_absolute(current_height.clone(), parent_height));
             }
             (Some(width), None) => {
-                self.width = Some(
-                    width.to_absolute(self.width.clone().unwrap_or(default_width), parent_width),
-                );
+                self.width = Some(width.to_absolute(current_width.clone(), parent_width));
                 self.height = None;
             }
             (None, Some(height)) => {
                 self.width = None;
-                self.height = Some(
-                    height
-                        .to_absolute(self.height.clone().unwrap_or(default_height), parent_height),
-                );
+                self.height = Some(height.to_absolute(current_height.clone(), parent_height));
             }
             _ => {}
         }
     }
 
+    /// Add the nudges of an update, with percentages measured against the area the window is
+    /// placed in
+    ///
+    /// This happens after `update`, once the padding of the updated state is known.
+    pub fn nudge(
+        &mut self,
+        nudge_x: Option<RelativeUnit>,
+        nudge_y: Option<RelativeUnit>,
+        width: i32,
+        height: i32,
+    ) {
+        if let Some(nudge_x) = nudge_x {
+            self.nudge_x += nudge_x.to_pixels(width);
+        }
+        if let Some(nudge_y) = nudge_y {
+            self.nudge_y += nudge_y.to_pixels(height);
+        }
+    }
+
+    /// Rebuild a state from the form the daemon reports and saves it in (see `From<State>`)
+    pub fn from_saved(mut saved: StateUpdate) -> Result<Self, DaemonError> {
+        // the nudges are saved in pixels, so the area doesn't matter
+        let (nudge_x, nudge_y) = (saved.nudge_x.take(), saved.nudge_y.take());
+        let mut state = Self::with_initial(saved.try_into()?);
+        state.nudge(nudge_x, nudge_y, 0, 0);
+
+        Ok(state)
+    }
+
     pub fn with_initial(initial: InitialStateOptions) -> Self {
+        let mut fullscreen = FullscreenOptions::default();
+        fullscreen.update(
+            initial.on_fullscreen,
+            initial.fullscreen_width,
+            initial.fullscreen_position,
+        );
+
         Self {
             position: Position(
                 initial.position.0.unwrap_or_default(),
@@ -118,11 +217,19 @@ impl State {
             width: initial.width,
             height: initial.height,
             natural: initial.natural.unwrap_or_default(),
+            aspect: initial.aspect.unwrap_or_default(),
+            layout: initial.layout.unwrap_or_default(),
+            offset_x: initial.offset_x,
+            offset_y: initial.offset_y,
+            nudge_x: 0,
+            nudge_y: 0,
+            fullscreen,
+            node_id: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
 pub enum Vertical {
     /// Top-aligned in the top third of the space
@@ -130,10 +237,11 @@ pub enum Vertical {
     /// Centered on the middle third of the space
     Middle,
     /// Bottom-aligned in the bottom third of the space
+    #[default]
     Bottom,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
 pub enum Horizontal {
     /// Left-aligned in the left third of the space
@@ -141,22 +249,25 @@ pub enum Horizontal {
     /// Centered on the middle third of the space
     Middle,
     /// Right-aligned in the right third of the space
+    #[default]
     Right,
 }
 
-impl Default for Vertical {
-    fn default() -> Self {
-        Self::Bottom
-    }
-}
-
-impl Default for Horizontal {
-    fn default() -> Self {
-        Self::Right
-    }
+/// How the floating windows on the workspace are arranged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// Only the targeted window is placed
+    #[default]
+    Single,
+    /// All floating windows are lined up along one edge
+    ///
+    /// Windows form a column when placed in the middle of the left or right edge, and a row
+    /// otherwise.
+    Strip,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct Position(pub Vertical, pub Horizontal);
 
 impl Position {
@@ -182,10 +293,20 @@ impl From<Position> for PositionUpdate {
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct StateUpdate {
     pub position: PositionUpdate,
-    pub padding: Option<u32>,
+    pub padding: Option<AbsoluteUnit>,
     pub width: Option<Unit>,
     pub height: Option<Unit>,
     pub natural: Option<bool>,
+    pub aspect: Option<Aspect>,
+    pub layout: Option<Layout>,
+    pub offset_x: Option<RelativeUnit>,
+    pub offset_y: Option<RelativeUnit>,
+    pub nudge_x: Option<RelativeUnit>,
+    pub nudge_y: Option<RelativeUnit>,
+    pub on_fullscreen: Option<FullscreenAction>,
+    pub fullscreen_width: Option<AbsoluteUnit>,
+    #[serde(default)]
+    pub fullscreen_position: PositionUpdate,
 }
 
 impl From<State> for StateUpdate {
@@ -196,6 +317,15 @@ impl From<State> for StateUpdate {
             width: state.width.map(Unit::Absolute),
             height: state.height.map(Unit::Absolute),
             natural: Some(state.natural),
+            aspect: Some(state.aspect),
+            layout: Some(state.layout),
+            offset_x: state.offset_x,
+            offset_y: state.offset_y,
+            nudge_x: Some(RelativePixels(state.nudge_x).into()),
+            nudge_y: Some(RelativePixels(state.nudge_y).into()),
+            on_fullscreen: Some(state.fullscreen.action),
+            fullscreen_width: Some(state.fullscreen.width),
+            fullscreen_position: state.fullscreen.position.into(),
         }
     }
 }
@@ -205,6 +335,11 @@ pub enum StateUpdateError {
     SwayIPC(swayipc::Error),
     NoApplicableNode,
     MultipleApplicableNodes,
+    /// Some of the commands in a command list failed
+    CommandsFailed {
+        total: usize,
+        failures: Vec<String>,
+    },
 }
 
 impl std::fmt::Display for StateUpdateError {
@@ -215,6 +350,18 @@ impl std::fmt::Display for StateUpdateError {
             StateUpdateError::MultipleApplicableNodes => {
                 write!(f, "Multiple applicable nodes found")
             }
+            StateUpdateError::CommandsFailed { total, failures } if failures.len() < *total => {
+                write!(
+                    f,
+                    "Only partially applied, {} of {} sway commands failed: {}",
+                    failures.len(),
+                    total,
+                    failures.join("; ")
+                )
+            }
+            StateUpdateError::CommandsFailed { failures, .. } => {
+                write!(f, "Sway commands failed: {}", failures.join("; "))
+            }
         }
     }
 }
@@ -233,3 +380,94 @@ impl From<SwayIPCError> for StateUpdateError {
         Self::SwayIPC(err)
     }
 }
+
+impl From<CommandError> for StateUpdateError {
+    fn from(err: CommandError) -> Self {
+        match err {
+            CommandError::SwayIPC(err) => Self::SwayIPC(err),
+            CommandError::Rejected { total, failures } => Self::CommandsFailed { total, failures },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use swayipc::Rect;
+
+    use crate::{
+        daemon::unit::{Density, RelativePercentage},
+        sway::WindowDimension,
+    };
+
+    use super::*;
+
+    fn window() -> Window {
+        // sway's rects can only be built by deserializing them
+        let area: Rect =
+            serde_json::from_str(r#"{"x": 0, "y": 0, "width": 1000, "height": 500}"#).unwrap();
+
+        Window {
+            dimensions: WindowDimension {
+                width: 200,
+                height: 100,
+            },
+            natural_dimensions: None,
+            working_area: area,
+            output_area: area,
+            horizontal_density: Density::default(),
+            vertical_density: Density::default(),
+        }
+    }
+
+    #[test]
+    fn test_nudges_accumulate_until_anchored() {
+        let context = window();
+        let mut state = State::default();
+
+        state.update(StateUpdate::default(), &context);
+        state.nudge(Some(RelativePixels(20).into()), None, 800, 400);
+        state.update(StateUpdate::default(), &context);
+        state.nudge(
+            Some(RelativePixels(-5).into()),
+            Some(RelativePercentage(10.0).into()),
+            800,
+            400,
+        );
+        assert_eq!((state.nudge_x, state.nudge_y), (15, 40));
+
+        // nudges that don't anchor the window again keep adding up
+        let update = StateUpdate {
+            width: Some(AbsolutePixels(300).into()),
+            ..Default::default()
+        };
+        state.update(update, &context);
+        assert_eq!((state.nudge_x, state.nudge_y), (15, 40));
+
+        let update = StateUpdate {
+            position: PositionUpdate(Some(Vertical::Top), None),
+            ..Default::default()
+        };
+        state.update(update, &context);
+        assert_eq!((state.nudge_x, state.nudge_y), (0, 0));
+
+        state.nudge(Some(RelativePixels(10).into()), None, 800, 400);
+        let update = StateUpdate {
+            offset_y: Some(RelativePixels(30).into()),
+            ..Default::default()
+        };
+        state.update(update, &context);
+        assert_eq!((state.nudge_x, state.nudge_y), (0, 0));
+    }
+
+    #[test]
+    fn test_from_command_error() {
+        let err = StateUpdateError::from(CommandError::Rejected {
+            total: 2,
+            failures: vec!["Unknown/invalid command".to_string()],
+        });
+        assert!(matches!(
+            err,
+            StateUpdateError::CommandsFailed { total: 2, ref failures } if failures.len() == 1
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    env,
+    fmt::Display,
+    sync::{Arc, Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{daemon::state::StateUpdate, discovery::format_uptime};
+
+/// The status of the daemon, shared between the main loop and the threads reporting to it
+pub type SharedStatus = Arc<Mutex<DaemonStatus>>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    /// When the daemon started, in seconds since the unix epoch
+    pub started: u64,
+    /// The socket of the sway session the daemon belongs to
+    pub sway_socket: Option<String>,
+    /// Whether the connection used to send commands to sway is up
+    pub sway_connected: bool,
+    /// Whether the connection used to listen for sway events is up
+    pub subscription_connected: bool,
+    /// How many times a connection to sway had to be re-established
+    pub reconnects: u32,
+    pub last_error: Option<String>,
+    pub state: Option<StateUpdate>,
+}
+
+impl DaemonStatus {
+    /// How long the daemon has been running, in seconds
+    pub fn uptime(&self) -> u64 {
+        now().saturating_sub(self.started)
+    }
+
+    pub fn shared() -> SharedStatus {
+        Arc::new(Mutex::new(Self {
+            pid: std::process::id(),
+            started: now(),
+            sway_socket: env::var("SWAYSOCK").ok(),
+            sway_connected: true,
+            subscription_connected: true,
+            ..Default::default()
+        }))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Update the shared status, even if another thread panicked while holding it
+pub fn update_status(status: &SharedStatus, update: impl FnOnce(&mut DaemonStatus)) {
+    update(&mut status.lock().unwrap_or_else(PoisonError::into_inner));
+}
+
+impl Display for DaemonStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let connected = |up: bool| if up { "connected" } else { "disconnected" };
+
+        writeln!(f, "pid: {}", self.pid)?;
+        writeln!(f, "uptime: {}", format_uptime(self.uptime()))?;
+        writeln!(
+            f,
+            "sway session: {}",
+            self.sway_socket.as_deref().unwrap_or("unknown")
+        )?;
+        writeln!(f, "sway commands: {}", connected(self.sway_connected))?;
+        writeln!(f, "sway events: {}", connected(self.subscription_connected))?;
+        writeln!(f, "reconnects: {}", self.reconnects)?;
+        match &self.last_error {
+            Some(err) => writeln!(f, "last error: {}", err)?,
+            None => writeln!(f, "last error: none")?,
+        }
+        match &self.state {
+            Some(state) => write!(
+                f,
+                "state: {}",
+                serde_json::to_string(state).unwrap_or_default()
+            ),
+            None => write!(f, "state: none"),
+        }
+    }
+}
@@ -2,15 +2,37 @@ use std::{
     io,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::Sender,
+        mpsc::{channel, RecvTimeoutError, Sender},
         Arc,
     },
     thread,
     time::Duration,
 };
-use swayipc::{Connection, Error as SwayIPCError};
+use swayipc::{Connection, Error as SwayIPCError, WindowChange};
 
-use crate::daemon::{state::StateUpdate, DaemonEvent};
+use crate::{
+    daemon::{
+        fullscreen::FullscreenChange,
+        reconnect::{connect_with_backoff, sway_socket_gone},
+        state::StateUpdate,
+        status::{update_status, SharedStatus},
+        DaemonEvent,
+    },
+    debug, error, info, warn,
+};
+
+const SUBSCRIPTIONS: [swayipc::EventType; 5] = [
+    swayipc::EventType::Window,
+    swayipc::EventType::Shutdown,
+    swayipc::EventType::Workspace,
+    swayipc::EventType::Output,
+    swayipc::EventType::Tick,
+];
+
+/// How often to check whether the workspaces have stopped moving around
+const SETTLE_INTERVAL: Duration = Duration::from_millis(25);
+/// How many times to check before giving up on the workspaces settling
+const MAX_SETTLE_CHECKS: usize = 8;
 
 pub struct SwaySubscription {
     con: Connection,
@@ -19,66 +41,125 @@ pub struct SwaySubscription {
 }
 
 impl SwaySubscription {
-    pub fn init<T: std::convert::From<swayipc::Event> + Send + std::fmt::Debug + 'static>(
+    /// Subscribe to sway events, and forward the ones we care about once sway has settled
+    ///
+    /// Events are debounced: they are only forwarded after `delay` milliseconds have passed
+    /// without any new event, and bursts of the same event are collapsed into one.
+    pub fn init(
         con_factory: fn() -> Result<Connection, SwayIPCError>,
-        tx: Sender<T>,
+        tx: Sender<DaemonEvent>,
         delay: u64,
+        status: SharedStatus,
     ) -> Result<Self, io::Error> {
         let running = Arc::new(AtomicBool::new(true));
         let r = running.clone();
-        let sub_con = con_factory().map_err(|e| {
-            eprintln!("Failed to create sway connection: {}", e);
-            io::Error::new(io::ErrorKind::Other, e)
-        })?;
-        let tick_con = con_factory().map_err(|e| {
-            eprintln!("Failed to create sway connection: {}", e);
-            io::Error::new(io::ErrorKind::Other, e)
-        })?;
+        let connect = || {
+            con_factory().map_err(|e| {
+                error!("Failed to create sway connection: {}", e);
+                io::Error::other(e)
+            })
+        };
+        let sub_con = connect()?;
+        let tick_con = connect()?;
+        let mut settle_con = connect()?;
 
-        let _thread = thread::spawn(move || {
-            let subs = [
-                swayipc::EventType::Window,
-                swayipc::EventType::Shutdown,
-                swayipc::EventType::Workspace,
-                swayipc::EventType::Output,
-                swayipc::EventType::Tick,
-            ];
-
-            let stream = sub_con
-                .subscribe(subs)
-                .expect("Failed to subscribe to events");
-            for event in stream {
-                // eprintln!("Received event: {:?}", event.as_ref());
-                if !r.load(Ordering::SeqCst) {
-                    eprintln!("Sway listener is shutting down...");
-                    break;
+        let (debounce_tx, debounce_rx) = channel::<swayipc::Event>();
+        let quiet = Duration::from_millis(delay);
+
+        let shutdown_tx = tx.clone();
+        thread::spawn(move || {
+            // the subscription thread hangs up once it's done, which ends this loop too
+            while let Ok(event) = debounce_rx.recv() {
+                let mut pending = vec![event];
+                loop {
+                    match debounce_rx.recv_timeout(quiet) {
+                        Ok(event) => pending.push(event),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
                 }
 
-                match event {
-                    Ok(event) => {
-                        match &event {
-                            swayipc::Event::Workspace(event) => match event.change {
-                                swayipc::WorkspaceChange::Reload => {}
-                                _ => continue,
-                            },
-                            _ => continue,
-                        }
+                if !wait_for_settle(&mut settle_con) {
+                    // the connection probably broke along with sway, try again next time
+                    if let Ok(con) = con_factory() {
+                        settle_con = con;
+                    }
+                }
+
+                debug!(events = pending.len(); "Sway events settled");
+                for event in collapse(pending, event_key) {
+                    if tx.send(event.into()).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
 
-                        // HACK: Let sway settle for a moment.
-                        // Without this, the bar or other things may end up moving things around and throwing off
-                        // the math. I would expect that to trigger a window or workspace event, but it doesn't
-                        // appear to do so in my testing environment.
-                        thread::sleep(Duration::from_millis(delay));
+        let stream = sub_con.subscribe(SUBSCRIPTIONS).map_err(io::Error::other)?;
+        let _thread = thread::spawn(move || {
+            let mut stream = Some(stream);
 
-                        let _ = tx.send(event.into());
+            while r.load(Ordering::SeqCst) {
+                let events = match stream.take() {
+                    Some(events) => events,
+                    None => {
+                        let resubscribe = || con_factory()?.subscribe(SUBSCRIPTIONS);
+                        let stopped = || !r.load(Ordering::SeqCst);
+                        match connect_with_backoff("subscription", resubscribe, stopped) {
+                            Ok(events) => {
+                                update_status(&status, |status| {
+                                    status.subscription_connected = true;
+                                    status.reconnects += 1;
+                                });
+                                info!("Resubscribed to sway events.");
+                                events
+                            }
+                            // sway is gone or the daemon is shutting down, either way it's over
+                            Err(e) => {
+                                error!("Giving up on the sway subscription: {}", e);
+                                if sway_socket_gone() {
+                                    info!("Sway's socket is gone, shutting down.");
+                                }
+                                let _ = shutdown_tx.send(DaemonEvent::Shutdown);
+                                break;
+                            }
+                        }
                     }
-                    Err(_) => {
+                };
+
+                for event in events {
+                    if !r.load(Ordering::SeqCst) {
+                        debug!("Sway listener is shutting down...");
                         break;
                     }
+
+                    match event {
+                        // sway is going away, so there's nothing to debounce
+                        Ok(swayipc::Event::Shutdown(_)) => {
+                            info!("Sway is exiting, shutting down.");
+                            let _ = shutdown_tx.send(DaemonEvent::Shutdown);
+                            return;
+                        }
+                        Ok(event) => {
+                            if event_key(&event).is_some() && debounce_tx.send(event).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => {
+                            error!("Sway subscription failed: {}", e);
+                            update_status(&status, |status| {
+                                status.subscription_connected = false;
+                                status.last_error =
+                                    Some(format!("Sway subscription failed: {}", e));
+                            });
+                            break;
+                        }
+                    }
                 }
             }
 
-            eprintln!("Sway subscription was closed.");
+            update_status(&status, |status| status.subscription_connected = false);
+            debug!("Sway subscription was closed.");
         });
 
         Ok(Self {
@@ -100,8 +181,153 @@ impl Drop for SwaySubscription {
     }
 }
 
+/// Identifies the events that we react to, and which of them are repeats of each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EventKey {
+    Reload,
+    /// An output was added, removed, or changed its mode, scale or transform
+    Output,
+    Window(WindowChange, i64),
+}
+
+fn event_key(event: &swayipc::Event) -> Option<EventKey> {
+    match event {
+        swayipc::Event::Workspace(event) => match event.change {
+            swayipc::WorkspaceChange::Reload => Some(EventKey::Reload),
+            _ => None,
+        },
+        // sway doesn't say what changed about the output, so any change could move the workspaces
+        swayipc::Event::Output(_) => Some(EventKey::Output),
+        swayipc::Event::Window(event) => match event.change {
+            WindowChange::FullscreenMode
+            | WindowChange::Close
+            | WindowChange::New
+            | WindowChange::Floating => Some(EventKey::Window(event.change, event.container.id)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Only keep the last of each repeated event, in the order they were last seen
+fn collapse<T, K: PartialEq>(events: Vec<T>, key: impl Fn(&T) -> K) -> Vec<T> {
+    let keys: Vec<K> = events.iter().map(&key).collect();
+
+    events
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !keys[i + 1..].contains(&keys[*i]))
+        .map(|(_, event)| event)
+        .collect()
+}
+
+/// Wait until the workspaces stop changing size, so things like the bar are done moving around
+///
+/// Returns false if sway couldn't be asked about the workspaces.
+fn wait_for_settle(con: &mut Connection) -> bool {
+    let snapshot = |con: &mut Connection| {
+        con.get_workspaces().map(|workspaces| {
+            workspaces
+                .iter()
+                .map(|workspace| {
+                    let rect = workspace.rect;
+                    (workspace.id, rect.x, rect.y, rect.width, rect.height)
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let Ok(mut previous) = snapshot(con) else {
+        return false;
+    };
+    for _ in 0..MAX_SETTLE_CHECKS {
+        thread::sleep(SETTLE_INTERVAL);
+
+        let Ok(current) = snapshot(con) else {
+            return false;
+        };
+        if current == previous {
+            return true;
+        }
+        previous = current;
+    }
+
+    warn!("Sway didn't settle, placing windows anyway");
+    true
+}
+
 impl From<swayipc::Event> for DaemonEvent {
-    fn from(_: swayipc::Event) -> Self {
-        DaemonEvent::Update(StateUpdate::default())
+    fn from(event: swayipc::Event) -> Self {
+        match event {
+            swayipc::Event::Window(event) => match event.change {
+                WindowChange::FullscreenMode => DaemonEvent::Fullscreen(FullscreenChange {
+                    con_id: event.container.id,
+                    active: matches!(event.container.fullscreen_mode, Some(1 | 2)),
+                    closed: false,
+                }),
+                // a closed window can't be fullscreen anymore
+                WindowChange::Close => DaemonEvent::Fullscreen(FullscreenChange {
+                    con_id: event.container.id,
+                    active: false,
+                    closed: true,
+                }),
+                WindowChange::New | WindowChange::Floating => DaemonEvent::Reflow,
+                _ => DaemonEvent::Update(StateUpdate::default()),
+            },
+            swayipc::Event::Workspace(_) | swayipc::Event::Output(_) => DaemonEvent::Reapply,
+            _ => DaemonEvent::Update(StateUpdate::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapse() {
+        let events = vec![(1, 'a'), (2, 'b'), (1, 'c'), (3, 'd'), (2, 'e')];
+        let collapsed = collapse(events, |event| event.0);
+
+        assert_eq!(collapsed, vec![(1, 'c'), (3, 'd'), (2, 'e')]);
+        assert!(collapse(Vec::<(i32, char)>::new(), |event| event.0).is_empty());
+    }
+
+    #[test]
+    fn test_output_event() {
+        let output: swayipc::OutputEvent =
+            serde_json::from_str(r#"{"change": "unspecified"}"#).unwrap();
+        let event = swayipc::Event::Output(output);
+
+        assert_eq!(event_key(&event), Some(EventKey::Output));
+        assert!(matches!(DaemonEvent::from(event), DaemonEvent::Reapply));
+    }
+
+    fn window_event(change: &str, fullscreen_mode: u8) -> swayipc::Event {
+        let rect = r#"{"x": 0, "y": 0, "width": 0, "height": 0}"#;
+        let json = format!(
+            r#"{{"change": "{change}", "container": {{"id": 7, "type": "floating_con",
+                "border": "normal", "current_border_width": 0, "layout": "none",
+                "orientation": "none", "rect": {rect}, "window_rect": {rect},
+                "deco_rect": {rect}, "geometry": {rect}, "urgent": false, "focused": false,
+                "focus": [], "nodes": [], "floating_nodes": [], "sticky": false,
+                "marks": [], "fullscreen_mode": {fullscreen_mode}}}}}"#
+        );
+
+        swayipc::Event::Window(Box::new(serde_json::from_str(&json).unwrap()))
+    }
+
+    #[test]
+    fn test_window_closed_or_leaving_fullscreen() {
+        let DaemonEvent::Fullscreen(left) = DaemonEvent::from(window_event("fullscreen_mode", 0))
+        else {
+            panic!("leaving fullscreen should be a fullscreen change");
+        };
+        assert!(!left.active && !left.closed);
+
+        let DaemonEvent::Fullscreen(closed) = DaemonEvent::from(window_event("close", 1)) else {
+            panic!("closing a window should be a fullscreen change");
+        };
+        assert!(!closed.active && closed.closed);
     }
 }
@@ -8,6 +8,8 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
+use crate::daemon::expression::Expression;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AbsolutePixels(pub u32);
 
@@ -72,6 +74,32 @@ impl Display for AbsolutePixels {
     }
 }
 
+/// A size in device pixels, which differ from (logical) pixels on scaled outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DevicePixels(pub u32);
+
+impl DevicePixels {
+    pub fn as_absolute_pixels(&self, density: &Density) -> AbsolutePixels {
+        AbsolutePixels((self.0 as f32 / density.scale).round() as u32)
+    }
+}
+
+impl FromStr for DevicePixels {
+    type Err = ParseUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.strip_suffix("dpx").unwrap_or(s);
+        let parsed_value: u32 = value.parse()?;
+        Ok(Self(parsed_value))
+    }
+}
+
+impl Display for DevicePixels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}dpx", self.0)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RelativePixels(pub i32);
 
@@ -258,11 +286,12 @@ impl Display for RelativePercentage {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Unit {
     /// A relative dimension, which can be a percentage or a pixel value (ex: `+100px` or `-5%`)
     Relative(RelativeUnit),
-    /// An absolute dimension, which can be a percentage or a pixel value (ex: `100px` or `33.333%`)
+    /// An absolute dimension, which can be a percentage, a pixel value, or an expression (ex:
+    /// `100px`, `33.333%` or `calc(50% - 20px)`)
     Absolute(AbsoluteUnit),
 }
 
@@ -316,7 +345,23 @@ impl Unit {
             Self::Relative(relative) => relative,
         };
 
+        let baseline = match baseline {
+            AbsoluteUnit::Expression(expression) => AbsoluteUnit::Pixels(AbsolutePixels(
+                expression.evaluate(container_px.0 as i32).max(0.0).round() as u32,
+            )),
+            AbsoluteUnit::Physical(_) | AbsoluteUnit::DevicePixels(_) => {
+                baseline.resolve(&Density::default())
+            }
+            baseline => baseline,
+        };
+
         match (baseline, relative) {
+            (
+                AbsoluteUnit::Expression(_)
+                | AbsoluteUnit::Physical(_)
+                | AbsoluteUnit::DevicePixels(_),
+                _,
+            ) => unreachable!(),
             (AbsoluteUnit::Pixels(current), RelativeUnit::Pixels(pixels)) => {
                 (current + *pixels).into()
             }
@@ -335,12 +380,37 @@ impl Unit {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AbsoluteUnit {
     /// A dimension in pixels (ex: `100` or `100px`)
     Pixels(AbsolutePixels),
     /// A dimension as a percentage (ex: `33.333%`)
     Percentage(AbsolutePercentage),
+    /// A dimension computed from the container size (ex: `calc(50% - 20px)` or `min(40%, 800px)`)
+    Expression(Expression),
+    /// A physical dimension on the output (ex: `60mm`, `6cm` or `2.5in`)
+    Physical(PhysicalLength),
+    /// A dimension in device pixels, regardless of the output scale (ex: `600dpx`)
+    DevicePixels(DevicePixels),
+}
+
+impl AbsoluteUnit {
+    /// Convert any physical lengths and device pixels into (logical) pixels, using the density
+    /// and scale of the output
+    pub fn resolve(&self, density: &Density) -> Self {
+        match self {
+            Self::Physical(length) => Self::Pixels(length.as_absolute_pixels(density)),
+            Self::DevicePixels(pixels) => Self::Pixels(pixels.as_absolute_pixels(density)),
+            Self::Expression(expression) => Self::Expression(expression.resolve(density)),
+            unit => unit.clone(),
+        }
+    }
+}
+
+impl Default for AbsoluteUnit {
+    fn default() -> Self {
+        Self::Pixels(AbsolutePixels(0))
+    }
 }
 
 impl From<AbsolutePixels> for AbsoluteUnit {
@@ -355,7 +425,127 @@ impl From<AbsolutePercentage> for AbsoluteUnit {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl From<Expression> for AbsoluteUnit {
+    fn from(value: Expression) -> Self {
+        Self::Expression(value)
+    }
+}
+
+impl From<PhysicalLength> for AbsoluteUnit {
+    fn from(value: PhysicalLength) -> Self {
+        Self::Physical(value)
+    }
+}
+
+impl From<DevicePixels> for AbsoluteUnit {
+    fn from(value: DevicePixels) -> Self {
+        Self::DevicePixels(value)
+    }
+}
+
+const MM_PER_INCH: f32 = 25.4;
+/// The reference density used by CSS, for outputs that don't report their physical size
+const DEFAULT_DPI: f32 = 96.0;
+
+/// How many logical pixels make up a millimeter along one axis of an output, and how many device
+/// pixels make up a logical pixel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Density {
+    pub px_per_mm: f32,
+    pub scale: f32,
+}
+
+impl Default for Density {
+    fn default() -> Self {
+        Self {
+            px_per_mm: DEFAULT_DPI / MM_PER_INCH,
+            scale: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PhysicalUnit {
+    Millimeters,
+    Centimeters,
+    Inches,
+}
+
+impl PhysicalUnit {
+    const SUFFIXES: [(&'static str, Self); 3] = [
+        ("mm", Self::Millimeters),
+        ("cm", Self::Centimeters),
+        ("in", Self::Inches),
+    ];
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Millimeters => "mm",
+            Self::Centimeters => "cm",
+            Self::Inches => "in",
+        }
+    }
+
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        Self::SUFFIXES
+            .iter()
+            .find(|(s, _)| *s == suffix)
+            .map(|(_, unit)| *unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PhysicalLength {
+    pub value: f32,
+    pub unit: PhysicalUnit,
+}
+
+impl PhysicalLength {
+    pub fn as_millimeters(&self) -> f32 {
+        match self.unit {
+            PhysicalUnit::Millimeters => self.value,
+            PhysicalUnit::Centimeters => self.value * 10.0,
+            PhysicalUnit::Inches => self.value * MM_PER_INCH,
+        }
+    }
+
+    pub fn as_pixels(&self, density: &Density) -> f32 {
+        self.as_millimeters() * density.px_per_mm
+    }
+
+    pub fn as_absolute_pixels(&self, density: &Density) -> AbsolutePixels {
+        AbsolutePixels(self.as_pixels(density).max(0.0).round() as u32)
+    }
+
+    fn split_suffix(s: &str) -> Option<(&str, PhysicalUnit)> {
+        PhysicalUnit::SUFFIXES
+            .iter()
+            .find_map(|(suffix, unit)| s.strip_suffix(suffix).map(|value| (value, *unit)))
+    }
+}
+
+impl FromStr for PhysicalLength {
+    type Err = ParseUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((value, unit)) = Self::split_suffix(s) else {
+            return Err(ParseUnitError::InvalidPhysicalUnit(s.to_string()));
+        };
+
+        Ok(Self {
+            value: value.parse()?,
+            unit,
+        })
+    }
+}
+
+impl Display for PhysicalLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.value, self.unit.suffix())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RelativeUnit {
     /// A relative dimension in pixels (ex: `+100` or `-100px`)
     Pixels(RelativePixels),
@@ -363,6 +553,18 @@ pub enum RelativeUnit {
     Percentage(RelativePercentage),
 }
 
+impl RelativeUnit {
+    /// The value in pixels, with percentages measured against the container
+    pub fn to_pixels(&self, container_px: i32) -> i32 {
+        match self {
+            Self::Pixels(pixels) => pixels.0,
+            Self::Percentage(percentage) => {
+                (percentage.0 / 100.0 * container_px as f32).round() as i32
+            }
+        }
+    }
+}
+
 impl From<RelativePixels> for RelativeUnit {
     fn from(value: RelativePixels) -> Self {
         Self::Pixels(value)
@@ -375,10 +577,62 @@ impl From<RelativePercentage> for RelativeUnit {
     }
 }
 
+/// An explicit aspect ratio to lock the window to (ex: `16:9`, `1.777`, or `none`)
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Aspect {
+    /// No explicit ratio, so the natural or current ratio of the window is used
+    #[default]
+    None,
+    /// A width / height ratio
+    Ratio(f32),
+}
+
+impl Aspect {
+    pub fn ratio(&self) -> Option<f32> {
+        match self {
+            Self::None => None,
+            Self::Ratio(ratio) => Some(*ratio),
+        }
+    }
+}
+
+impl FromStr for Aspect {
+    type Err = ParseUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("none") {
+            return Ok(Self::None);
+        }
+
+        let ratio = match s.split_once(':') {
+            Some((width, height)) => width.trim().parse::<f32>()? / height.trim().parse::<f32>()?,
+            None => s.parse()?,
+        };
+
+        if !ratio.is_finite() || ratio <= 0.0 {
+            return Err(ParseUnitError::InvalidRatio(s.to_string()));
+        }
+
+        Ok(Self::Ratio(ratio))
+    }
+}
+
+impl Display for Aspect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Ratio(ratio) => write!(f, "{}", ratio),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ParseUnitError {
     ParseIntError(ParseIntError),
     ParseFloatError(std::num::ParseFloatError),
+    InvalidRatio(String),
+    InvalidExpression(String),
+    InvalidPhysicalUnit(String),
 }
 
 impl Display for ParseUnitError {
@@ -386,6 +640,13 @@ impl Display for ParseUnitError {
         match self {
             ParseUnitError::ParseIntError(err) => write!(f, "ParseIntError: {}", err),
             ParseUnitError::ParseFloatError(err) => write!(f, "ParseFloatError: {}", err),
+            ParseUnitError::InvalidRatio(ratio) => write!(f, "Invalid aspect ratio: {}", ratio),
+            ParseUnitError::InvalidExpression(expression) => {
+                write!(f, "Invalid expression: {}", expression)
+            }
+            ParseUnitError::InvalidPhysicalUnit(length) => {
+                write!(f, "Invalid physical length: {}", length)
+            }
         }
     }
 }
@@ -395,6 +656,9 @@ impl Error for ParseUnitError {
         match self {
             ParseUnitError::ParseIntError(err) => Some(err),
             ParseUnitError::ParseFloatError(err) => Some(err),
+            ParseUnitError::InvalidRatio(_)
+            | ParseUnitError::InvalidExpression(_)
+            | ParseUnitError::InvalidPhysicalUnit(_) => None,
         }
     }
 }
@@ -457,8 +721,14 @@ impl FromStr for AbsoluteUnit {
     type Err = ParseUnitError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.strip_suffix("%").is_some() {
+        if Expression::is_expression(s) {
+            Ok(Self::Expression(s.parse()?))
+        } else if s.strip_suffix("%").is_some() {
             Ok(Self::Percentage(s.parse()?))
+        } else if s.ends_with("dpx") {
+            Ok(Self::DevicePixels(s.parse()?))
+        } else if PhysicalLength::split_suffix(s).is_some() {
+            Ok(Self::Physical(s.parse()?))
         } else {
             // Default to pixels if no suffix is provided
             Ok(Self::Pixels(s.parse()?))
@@ -471,6 +741,9 @@ impl Display for AbsoluteUnit {
         match self {
             Self::Pixels(value) => write!(f, "{}", value),
             Self::Percentage(value) => write!(f, "{}", value),
+            Self::Expression(value) => write!(f, "{}", value),
+            Self::Physical(value) => write!(f, "{}", value),
+            Self::DevicePixels(value) => write!(f, "{}", value),
         }
     }
 }
@@ -527,6 +800,93 @@ mod tests {
         assert!(RelativePercentage::from_str("10px").is_err());
     }
 
+    #[test]
+    fn test_aspect_from_str() {
+        assert_eq!(Aspect::from_str("none").unwrap(), Aspect::None);
+        assert_eq!(Aspect::from_str("16:9").unwrap(), Aspect::Ratio(16.0 / 9.0));
+        assert_eq!(Aspect::from_str("4 : 3").unwrap(), Aspect::Ratio(4.0 / 3.0));
+        assert_eq!(Aspect::from_str("1.777").unwrap(), Aspect::Ratio(1.777));
+        assert!(Aspect::from_str("16:0").is_err());
+        assert!(Aspect::from_str("-1.5").is_err());
+        assert!(Aspect::from_str("wide").is_err());
+    }
+
+    #[test]
+    fn test_expression_unit_from_str() {
+        let unit = Unit::from_str("calc(50% - 20px)").unwrap();
+        assert!(matches!(
+            unit,
+            Unit::Absolute(AbsoluteUnit::Expression(Expression::Sub(..)))
+        ));
+
+        let serialized = serde_json::to_string(&unit).unwrap();
+        assert_eq!(serde_json::from_str::<Unit>(&serialized).unwrap(), unit);
+
+        let baseline = AbsoluteUnit::from_str("min(40%, 800px)").unwrap();
+        let relative = Unit::from_str("+5%").unwrap();
+        assert_eq!(
+            relative.to_absolute(baseline, 1000u32),
+            AbsolutePercentage(45.0).into()
+        );
+    }
+
+    #[test]
+    fn test_physical_length_from_str() {
+        assert_eq!(
+            AbsoluteUnit::from_str("60mm").unwrap(),
+            PhysicalLength {
+                value: 60.0,
+                unit: PhysicalUnit::Millimeters
+            }
+            .into()
+        );
+        assert_eq!(
+            AbsoluteUnit::from_str("2.5in").unwrap(),
+            PhysicalLength {
+                value: 2.5,
+                unit: PhysicalUnit::Inches
+            }
+            .into()
+        );
+        assert!(PhysicalLength::from_str("6px").is_err());
+        assert!(PhysicalLength::from_str("cm").is_err());
+    }
+
+    #[test]
+    fn test_resolve_physical_length() {
+        let density = Density {
+            px_per_mm: 4.0,
+            scale: 1.5,
+        };
+
+        assert_eq!(
+            AbsoluteUnit::from_str("6cm").unwrap().resolve(&density),
+            AbsolutePixels(240).into()
+        );
+        assert_eq!(
+            AbsoluteUnit::from_str("1in")
+                .unwrap()
+                .resolve(&Density::default()),
+            AbsolutePixels(96).into()
+        );
+        assert_eq!(
+            AbsoluteUnit::from_str("calc(50% - 5mm)")
+                .unwrap()
+                .resolve(&density),
+            AbsoluteUnit::from_str("calc(50% - 20px)").unwrap()
+        );
+        assert_eq!(
+            AbsoluteUnit::from_str("600dpx").unwrap().resolve(&density),
+            AbsolutePixels(400).into()
+        );
+        assert_eq!(
+            AbsoluteUnit::from_str("calc(100% - 30dpx)")
+                .unwrap()
+                .resolve(&density),
+            AbsoluteUnit::from_str("calc(100% - 20px)").unwrap()
+        );
+    }
+
     #[test]
     fn test_adding_units() {
         let abs_px1 = AbsolutePixels::from(100u32);
@@ -0,0 +1,158 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const SOCKET_EXTENSION: &str = "sock";
+
+/// The directory that every daemon puts its socket in
+pub fn socket_dir() -> PathBuf {
+    PathBuf::from(env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "./".to_string()))
+        .join("sway-gravity")
+}
+
+/// The socket of the daemon for the sway session we are running in, which is where it listens
+pub fn session_socket() -> PathBuf {
+    let swaysock = env::var("SWAYSOCK").ok();
+    let wayland_display = env::var("WAYLAND_DISPLAY").ok();
+
+    socket_dir().join(format!(
+        "{}.{}",
+        session_key(swaysock.as_deref(), wayland_display.as_deref()),
+        SOCKET_EXTENSION
+    ))
+}
+
+/// The socket that clients send their messages to
+///
+/// Outside of a sway session (ex: from a terminal on another TTY), there's no way to tell which
+/// daemon is meant, so the only running daemon is used if there's exactly one. Daemons never use
+/// this, as they would replace the daemon of another session.
+pub fn client_socket() -> PathBuf {
+    let in_session = env::var_os("SWAYSOCK").is_some();
+    pick_client_socket(session_socket(), in_session, &find_sockets(&socket_dir()))
+}
+
+fn pick_client_socket(session: PathBuf, in_session: bool, running: &[PathBuf]) -> PathBuf {
+    match running {
+        [socket] if !in_session => socket.clone(),
+        _ => session,
+    }
+}
+
+/// A name that is unique to a sway session
+///
+/// Sway's socket is named `sway-ipc.<uid>.<pid>.sock`, so the PID of sway is used when possible.
+/// Nested sways all share the same `WAYLAND_DISPLAY` of their parent, so that is only a fallback.
+fn session_key(swaysock: Option<&str>, wayland_display: Option<&str>) -> String {
+    let Some(swaysock) = swaysock else {
+        return wayland_display.unwrap_or("sway").to_string();
+    };
+
+    let name = Path::new(swaysock)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    match name.split('.').collect::<Vec<_>>().as_slice() {
+        ["sway-ipc", _, pid] if pid.parse::<u32>().is_ok() => format!("sway-{}", pid),
+        // sway was told to use a socket with some other name
+        _ => name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Every daemon socket in the given directory
+pub fn find_sockets(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut sockets: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == SOCKET_EXTENSION))
+        .collect();
+    sockets.sort();
+
+    sockets
+}
+
+/// Format a number of seconds like `2d 03h 04m 05s`, leaving out the larger units that are zero
+pub fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+    );
+
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {:02}s", minutes, seconds),
+        (0, _, _) => format!("{}h {:02}m {:02}s", hours, minutes, seconds),
+        _ => format!("{}d {:02}h {:02}m {:02}s", days, hours, minutes, seconds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_client_socket() {
+        let session = PathBuf::from("/run/sway-gravity/sway.sock");
+        let other = PathBuf::from("/run/sway-gravity/sway-1234.sock");
+
+        assert_eq!(
+            pick_client_socket(session.clone(), false, std::slice::from_ref(&other)),
+            other
+        );
+        // inside a sway session, only that session's daemon is used
+        assert_eq!(
+            pick_client_socket(session.clone(), true, std::slice::from_ref(&other)),
+            session
+        );
+        assert_eq!(
+            pick_client_socket(session.clone(), false, &[other.clone(), other.clone()]),
+            session
+        );
+        assert_eq!(pick_client_socket(session.clone(), false, &[]), session);
+    }
+
+    #[test]
+    fn test_session_key() {
+        assert_eq!(
+            session_key(
+                Some("/run/user/1000/sway-ipc.1000.4242.sock"),
+                Some("wayland-1")
+            ),
+            "sway-4242"
+        );
+        assert_eq!(
+            session_key(Some("/tmp/my sway.sock"), Some("wayland-1")),
+            "my_sway"
+        );
+        assert_eq!(session_key(None, Some("wayland-1")), "wayland-1");
+        assert_eq!(session_key(None, None), "sway");
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(5), "5s");
+        assert_eq!(format_uptime(65), "1m 05s");
+        assert_eq!(format_uptime(3600), "1h 00m 00s");
+        assert_eq!(
+            format_uptime(2 * 86400 + 3 * 3600 + 4 * 60 + 5),
+            "2d 03h 04m 05s"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use crate::{
+    client::request_status,
+    compute_placement,
+    daemon::state::{StateUpdate, StateUpdateError},
+    find_target_node, place_windows,
+    sway::SwayConnection,
+    warn, State,
+};
+
+/// Compute where the update would put the targeted window, and print it without moving anything
+///
+/// The update is applied on top of the running daemon's state, or the default state if no daemon
+/// is reachable. Only the single targeted window is considered, even with the strip layout.
+pub fn dry_run(socket: &PathBuf, update: StateUpdate) -> Result<(), StateUpdateError> {
+    let mut con = SwayConnection::new()?;
+    let target = find_target_node(&mut con)?;
+
+    let state = match request_status(socket) {
+        Ok(status) => match status.state.map(State::from_saved).transpose() {
+            Ok(state) => state.unwrap_or_default(),
+            Err(e) => {
+                warn!(
+                    "Couldn't use the daemon's state, starting from the default: {}",
+                    e
+                );
+                State::default()
+            }
+        },
+        Err(e) => {
+            warn!(
+                "Couldn't get the daemon's state, starting from the default: {}",
+                e
+            );
+            State::default()
+        }
+    };
+
+    let placement = compute_placement(&mut con, &target, state, update)?;
+
+    println!(
+        "target:       con_id {} ({})",
+        target.id,
+        target.name.as_deref().unwrap_or("unnamed")
+    );
+    println!("working area: {}", placement.working);
+    println!("padded area:  {}", placement.padded);
+    println!("aspect ratio: {}", placement.aspect);
+    println!("final rect:   {}", placement.rect);
+    println!("command:      {}", placement.command(&[]));
+
+    Ok(())
+}
+
+/// Place the targeted window straight from the client, without going through a daemon
+///
+/// The update is applied to the given state (the saved one, or the default state), so relative
+/// sizes (ex: `+5%`) resolve against the window's current size unless the state has a size.
+pub fn oneshot(state: State, update: StateUpdate) -> Result<(), StateUpdateError> {
+    let mut con = SwayConnection::new()?;
+    place_windows(&mut con, state, update)?;
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::{
+    env,
+    ffi::CString,
+    fmt::{Arguments, Display},
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+
+/// The environment variable that sets the log level, when `--log-level` isn't given
+pub const LOG_LEVEL_ENV: &str = "SWAY_GRAVITY_LOG";
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Level {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// The level from `--log-level`, falling back to `SWAY_GRAVITY_LOG`, and then to `info`
+    pub fn from_args_or_env(level: Option<Level>) -> Self {
+        level
+            .or_else(|| {
+                env::var(LOG_LEVEL_ENV)
+                    .ok()
+                    .and_then(|level| Level::from_str(&level, true).ok())
+            })
+            .unwrap_or_default()
+    }
+
+    fn syslog_priority(&self) -> libc::c_int {
+        match self {
+            Level::Error => libc::LOG_ERR,
+            Level::Warn => libc::LOG_WARNING,
+            Level::Info => libc::LOG_INFO,
+            Level::Debug | Level::Trace => libc::LOG_DEBUG,
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+
+        f.pad(name)
+    }
+}
+
+/// Where log lines end up
+#[derive(Debug)]
+pub enum Sink {
+    Stderr,
+    File(File),
+    Syslog,
+}
+
+impl Sink {
+    pub fn file(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::File(file))
+    }
+
+    pub fn syslog() -> Self {
+        // the identity has to outlive every call to `syslog`, so it is a static string
+        unsafe {
+            libc::openlog(c"sway-gravity".as_ptr(), libc::LOG_PID, libc::LOG_DAEMON);
+        }
+        Self::Syslog
+    }
+
+    fn write(&mut self, level: Level, line: &str) {
+        match self {
+            Sink::Stderr => eprintln!("[{:<5}] {}", level, line),
+            Sink::File(file) => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    file,
+                    "{}.{:03} [{:<5}] {}",
+                    timestamp.as_secs(),
+                    timestamp.subsec_millis(),
+                    level,
+                    line
+                );
+            }
+            Sink::Syslog => {
+                // syslog lines are C strings, so they can't contain any nul bytes
+                let Ok(line) = CString::new(line.replace('\0', "")) else {
+                    return;
+                };
+                unsafe {
+                    libc::syslog(level.syslog_priority(), c"%s".as_ptr(), line.as_ptr());
+                }
+            }
+        }
+    }
+}
+
+struct Logger {
+    level: Level,
+    sink: Mutex<Sink>,
+}
+
+/// Set up the global logger, which can only be done once
+pub fn init(level: Level, sink: Sink) {
+    let _ = LOGGER.set(Logger {
+        level,
+        sink: Mutex::new(sink),
+    });
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= LOGGER.get().map(|logger| logger.level).unwrap_or_default()
+}
+
+/// Write a log line, with any structured fields appended as `key=value` pairs
+pub fn write(level: Level, message: Arguments, fields: &[(&str, &dyn std::fmt::Debug)]) {
+    let line = format_line(message, fields);
+
+    match LOGGER.get() {
+        Some(logger) => logger
+            .sink
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(level, &line),
+        None => Sink::Stderr.write(level, &line),
+    }
+}
+
+fn format_line(message: Arguments, fields: &[(&str, &dyn std::fmt::Debug)]) -> String {
+    let mut line = message.to_string();
+    for (key, value) in fields {
+        line.push_str(&format!(" {}={:?}", key, value));
+    }
+
+    line
+}
+
+/// Log a message at the given level, with optional structured fields before the message
+///
+/// ```ignore
+/// log!(Level::Info, con_id = node.id, rect = rect; "Window moved to {}", position);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, format_args!($($arg)+), &[$((stringify!($key), &$value)),+]);
+        }
+    };
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, format_args!($($arg)+), &[]);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        let con_id = 42;
+        let line = format_line(
+            format_args!("Window moved to {}", "bottom right"),
+            &[("con_id", &con_id), ("event", &"new")],
+        );
+
+        assert_eq!(
+            line,
+            r#"Window moved to bottom right con_id=42 event="new""#
+        );
+    }
+
+    #[test]
+    fn test_level_order() {
+        assert!(Level::Error < Level::Warn);
+        assert!(Level::Info < Level::Debug);
+        assert_eq!(Level::from_args_or_env(Some(Level::Trace)), Level::Trace);
+        assert_eq!(format!("[{:<5}]", Level::Info), "[INFO ]");
+    }
+}
@@ -6,22 +6,31 @@ use swayipc::{Node, NodeType};
 
 use crate::{
     cli::Args,
-    client::{send_message, ClientError},
+    client::{forward_stdin, list_daemons, request_status, send_batch, send_message, ClientError},
     daemon::{
+        ipc::AccessControl,
+        persist::load_state,
         run_daemon,
         state::{
-            Horizontal, InitialStateOptions, Position, State, StateUpdate, StateUpdateError,
-            Vertical,
+            Horizontal, InitialStateOptions, Layout, Position, State, StateUpdate,
+            StateUpdateError, Vertical,
         },
-        unit::{AbsolutePixels, AbsoluteUnit, RelativeUnit, Unit},
+        unit::{AbsolutePixels, AbsoluteUnit, Density, RelativeUnit, Unit},
         DaemonError,
     },
-    sway::{Dimension, Window},
+    discovery::{client_socket, session_socket},
+    local::{dry_run, oneshot},
+    log::{Level, Sink},
+    sway::{node_command, Dimension, Window, SCRATCHPAD_OUTPUT},
 };
 
 mod cli;
 mod client;
 mod daemon;
+mod discovery;
+mod local;
+mod log;
+mod output;
 mod sway;
 
 #[derive(Debug)]
@@ -67,31 +76,79 @@ impl From<StateUpdateError> for ApplicationError {
 }
 
 fn submain(args: Args) -> Result<(), ApplicationError> {
-    let Ok(_) = env::var("WAYLAND_DISPLAY") else {
-        eprintln!("No WAYLAND_DISPLAY environment variable found");
-        return Ok(());
-    };
-    let socket = args.socket.clone();
-    let sway_delay = args.sway_event_delay;
+    // only clients may fall back to a daemon of another session, a daemon always gets its own
+    let socket = args.socket.clone().unwrap_or_else(|| {
+        if args.daemon {
+            session_socket()
+        } else {
+            client_socket()
+        }
+    });
+    let sway_delay = args.daemon_options.sway_event_delay;
 
     if args.daemon {
+        // clients can find their daemon from outside the session, but the daemon needs to be in it
+        let Ok(_) = env::var("WAYLAND_DISPLAY") else {
+            error!("No WAYLAND_DISPLAY environment variable found");
+            return Ok(());
+        };
+        let access = AccessControl::new(args.daemon_options.allow_uid.clone());
+        let state_file = args.state_file.clone();
         let initial: InitialStateOptions = args.try_into()?;
 
         Ok(run_daemon(
             socket,
             State::with_initial(initial),
             sway_delay,
+            access,
+            state_file,
         )?)
+    } else if args.list_daemons {
+        list_daemons();
+        Ok(())
+    } else if args.stdin {
+        Ok(forward_stdin(&socket)?)
+    } else if args.target.oneshot {
+        let state = match &args.state_file {
+            Some(path) => load_state(path).unwrap_or_else(|e| {
+                warn!(
+                    "Couldn't load the state from {}, starting from the default: {}",
+                    path.display(),
+                    e
+                );
+                State::default()
+            }),
+            None => State::default(),
+        };
+        Ok(oneshot(state, args.into())?)
+    } else if args.target.dry_run {
+        Ok(dry_run(&socket, args.into())?)
+    } else if args.batch {
+        Ok(send_batch(&socket)?)
+    } else if args.status {
+        println!("{}", request_status(&socket)?);
+        Ok(())
     } else {
         Ok(send_message(&socket, args.into())?)
     }
 }
 
 fn main() {
-    let args = Args::parse();
+    let args = Args::parse().normalize();
+
+    let level = Level::from_args_or_env(args.log.log_level);
+    let sink = match (&args.log.log_file, args.log.syslog) {
+        (Some(path), _) => Sink::file(path).unwrap_or_else(|e| {
+            error!("Failed to open the log file {}: {}", path.display(), e);
+            Sink::Stderr
+        }),
+        (None, true) => Sink::syslog(),
+        (None, false) => Sink::Stderr,
+    };
+    log::init(level, sink);
 
     if let Err(e) = submain(args) {
-        eprintln!("Error: {}", e);
+        error!("{}", e);
         std::process::exit(1);
     }
 }
@@ -99,14 +156,18 @@ fn main() {
 fn find_target_node(con: &mut SwayConnection) -> Result<swayipc::Node, StateUpdateError> {
     let tree = con.get_tree()?;
 
+    // windows hidden in the scratchpad live on the special `__i3` output
     let floating_nodes: Vec<_> = tree
+        .nodes
         .iter()
+        .filter(|output| output.name.as_deref() != Some(SCRATCHPAD_OUTPUT))
+        .flat_map(|output| output.iter())
         .filter(|node| node.node_type == NodeType::FloatingCon)
         .collect();
 
     let target_node = match floating_nodes.len() {
         1 => {
-            println!("Only one floating node found, using it.");
+            debug!(con_id = floating_nodes[0].id; "Only one floating node found, using it");
             let floating_node_id = floating_nodes[0].id;
             tree.find(|node| node.id == floating_node_id)
                 .expect("Node should exist")
@@ -120,59 +181,342 @@ fn find_target_node(con: &mut SwayConnection) -> Result<swayipc::Node, StateUpda
     Ok(target_node)
 }
 
+/// The window the state was last applied to, as long as it's still floating and not hidden
+///
+/// Unlike `find_target_node`, this doesn't depend on which window has the focus.
+fn find_placed_node(
+    con: &mut SwayConnection,
+    state: &State,
+) -> Result<Option<swayipc::Node>, StateUpdateError> {
+    let Some(node_id) = state.node_id else {
+        return Ok(None);
+    };
+
+    match con.find_node(node_id)? {
+        Some(node)
+            if node.node_type == NodeType::FloatingCon
+                && !con.is_node_in_scratchpad(node_id)? =>
+        {
+            Ok(Some(node))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Apply the update to the targeted window, or to every window in the strip
+fn place_windows(
+    con: &mut SwayConnection,
+    state: State,
+    update: StateUpdate,
+) -> Result<State, StateUpdateError> {
+    match update.layout.unwrap_or(state.layout) {
+        Layout::Single => {
+            let target = find_target_node(con)?;
+            move_window(con, target, state, update)
+        }
+        Layout::Strip => place_strip(con, state, update),
+    }
+}
+
 fn move_window(
     con: &mut SwayConnection,
     target_node: Node,
-    mut state: State,
+    state: State,
+    update: StateUpdate,
+) -> Result<State, StateUpdateError> {
+    move_window_with(con, target_node, state, update, &[])
+}
+
+/// Move the window, applying the extra actions (ex: `sticky enable`) in the same command
+fn move_window_with(
+    con: &mut SwayConnection,
+    target_node: Node,
+    state: State,
     update: StateUpdate,
+    extra: &[String],
 ) -> Result<State, StateUpdateError> {
+    let placement = compute_placement(con, &target_node, state, update)?;
+
+    con.run_commands(&placement.command(extra))?;
+    debug!(con_id = target_node.id, rect = placement.rect; "Placed window");
+
+    Ok(placement.state)
+}
+
+/// The sway actions that give a window its placement
+fn placement_actions(rect: &Rect) -> Vec<String> {
+    vec![
+        format!(
+            "resize set {} {}",
+            AbsolutePixels::from(rect.width.max(0) as u32),
+            AbsolutePixels::from(rect.height.max(0) as u32)
+        ),
+        format!("move position {} {}", rect.x, rect.y),
+    ]
+}
+
+/// Where a window should go, computed without changing anything yet
+struct Placement {
+    node_id: i64,
+    /// The state after the update
+    state: State,
+    working: Rect,
+    padded: Rect,
+    aspect: AspectSource,
+    /// The final size and position, relative to the workspace
+    rect: Rect,
+}
+
+impl Placement {
+    /// The sway command that applies the placement after the extra actions, all in one go so the
+    /// window isn't redrawn in between
+    fn command(&self, extra: &[String]) -> String {
+        let mut actions = extra.to_vec();
+        actions.extend(placement_actions(&self.rect));
+
+        node_command(self.node_id, &actions)
+    }
+}
+
+/// Compute the updated state, and where the window should end up
+fn compute_placement(
+    con: &mut SwayConnection,
+    target_node: &Node,
+    mut state: State,
+    mut update: StateUpdate,
+) -> Result<Placement, StateUpdateError> {
     let context = Window::from_node(target_node.clone(), con).map_err(StateUpdateError::SwayIPC)?;
+    let (nudge_x, nudge_y) = (update.nudge_x.take(), update.nudge_y.take());
+    state.update(update, &context);
+    state.node_id = Some(target_node.id);
+
+    let area = PlacementArea::new(&context, &state);
+    state.nudge(nudge_x, nudge_y, area.padded.width, area.padded.height);
+    let aspect = AspectSource::new(&context, &state);
+    let rect = compute_size(target_node, &context, &state, &area, aspect);
+    let rect = area.padded.get_pos_for_rect_of_size(&state.position, &rect);
+    let (x, y) = slide_within(&mut state, &area.padded, &rect);
+    let rect = rect.translate(x, y);
+
+    Ok(Placement {
+        node_id: target_node.id,
+        state,
+        working: area.working,
+        padded: area.padded,
+        aspect,
+        rect: area.to_workspace(&rect),
+    })
+}
+
+/// How far the window is moved from its anchor, including any nudges
+fn anchor_offset(state: &State, area: &Rect) -> (i32, i32) {
+    let x = state
+        .offset_x
+        .as_ref()
+        .map_or(0, |x| x.to_pixels(area.width));
+    let y = state
+        .offset_y
+        .as_ref()
+        .map_or(0, |y| y.to_pixels(area.height));
+
+    (x + state.nudge_x, y + state.nudge_y)
+}
+
+/// How far the rect can slide from its anchor by the offsets and nudges, without leaving the area
+///
+/// Only the part of the nudges that took effect is kept, so nudging into an edge doesn't build up
+/// a distance that has to be nudged back first.
+fn slide_within(state: &mut State, area: &Rect, rect: &Rect) -> (i32, i32) {
+    let (x, y) = anchor_offset(state, area);
+    let slid = rect.translate(x, y);
+    let kept = slid.keep_within(area.width, area.height);
+    state.nudge_x -= slid.x - kept.x;
+    state.nudge_y -= slid.y - kept.y;
+
+    (kept.x - rect.x, kept.y - rect.y)
+}
+
+/// Line up all the floating windows on the focused workspace along one edge
+fn place_strip(
+    con: &mut SwayConnection,
+    mut state: State,
+    mut update: StateUpdate,
+) -> Result<State, StateUpdateError> {
+    let nodes = find_strip_nodes(con)?;
+    let first = nodes.first().ok_or(StateUpdateError::NoApplicableNode)?;
+
+    // every window is on the same workspace, so any of them can provide the context for the update
+    let context = Window::from_node(first.clone(), con)?;
+    let (nudge_x, nudge_y) = (update.nudge_x.take(), update.nudge_y.take());
     state.update(update, &context);
+    let area = PlacementArea::new(&context, &state);
+    state.nudge(nudge_x, nudge_y, area.padded.width, area.padded.height);
+
+    let mut sizes = Vec::with_capacity(nodes.len());
+    for node in &nodes {
+        let context = Window::from_node(node.clone(), con)?;
+        let aspect = AspectSource::new(&context, &state);
+        sizes.push(compute_size(node, &context, &state, &area, aspect));
+    }
+
+    let placements = area
+        .padded
+        .arrange_strip(&state.position, &sizes, area.padding);
+    // the strip slides as a whole, so it stays in one piece at the edge
+    let (x, y) = slide_within(&mut state, &area.padded, &Rect::bounds(&placements));
+    let mut commands = Vec::with_capacity(nodes.len());
+    for (node, rect) in nodes.iter().zip(placements) {
+        let rect = area.to_workspace(&rect.translate(x, y));
+        debug!(con_id = node.id, rect = rect; "Placing window in strip");
+        commands.push(node_command(node.id, &placement_actions(&rect)));
+    }
+    con.run_commands(&commands.join("; "))?;
+
+    Ok(state)
+}
+
+/// The floating windows on the focused workspace, in a stable order
+fn find_strip_nodes(con: &mut SwayConnection) -> Result<Vec<Node>, StateUpdateError> {
+    let workspace = con
+        .get_workspaces()?
+        .into_iter()
+        .find(|workspace| workspace.focused)
+        .ok_or(StateUpdateError::NoApplicableNode)?;
+
+    let mut nodes = con
+        .get_tree()?
+        .find(|node| node.node_type == NodeType::Workspace && node.id == workspace.id)
+        .map(|workspace| workspace.floating_nodes)
+        .unwrap_or_default();
+    // ids only ever increase, so windows keep their spot as others come and go
+    nodes.sort_by_key(|node| node.id);
+
+    Ok(nodes)
+}
+
+/// The area of the workspace windows are placed in
+struct PlacementArea {
+    working: Rect,
+    output: Rect,
+    padded: Rect,
+    padding: i32,
+    scale: f32,
+}
+
+impl PlacementArea {
+    fn new(context: &Window, state: &State) -> Self {
+        let working: Rect = context.working_area.into();
+        let output: Rect = context.output_area.into();
+        let scale = context.horizontal_density.scale;
+        // percentages are relative to the shorter side, so the padding is the same on every edge
+        let padding = unit_to_real_pixels(
+            state.padding.resolve(&context.horizontal_density).into(),
+            0,
+            working.width.min(working.height),
+        );
+        // the device pixel grid starts at the corner of the output, not of the workspace
+        let padded = working.with_padding(padding).snap_to_device(&output, scale);
+
+        Self {
+            working,
+            output,
+            padded,
+            padding,
+            scale,
+        }
+    }
+
+    /// Convert a rect positioned inside the padded area to coordinates relative to the workspace
+    fn to_workspace(&self, rect: &Rect) -> Rect {
+        // we added a padding to our working area, but the center of the new area is not the same
+        // as the center of the old area, so we need to adjust the position of the window
+        rect.translate(
+            self.padded.x - self.working.x,
+            self.padded.y - self.working.y,
+        )
+        .snap_to_device(
+            &self.output.translate(-self.working.x, -self.working.y),
+            self.scale,
+        )
+    }
+}
+
+/// Where the aspect ratio of a window comes from
+#[derive(Debug, Clone, Copy)]
+enum AspectSource {
+    /// Given with `--aspect`
+    Explicit(f32),
+    /// The natural size of the window's content
+    Natural(f32),
+    /// Whatever the window currently has
+    Current,
+}
+
+impl AspectSource {
+    /// An explicit aspect ratio wins over the natural one, which wins over the current one
+    fn new(context: &Window, state: &State) -> Self {
+        if let Some(ratio) = state.aspect.ratio() {
+            return Self::Explicit(ratio);
+        }
 
-    let working_area: Rect = context.working_area.into();
-    let proper_area = working_area.with_padding(state.padding as i32);
+        match &context.natural_dimensions {
+            Some(natural) if state.natural => Self::Natural(natural.ratio()),
+            _ => Self::Current,
+        }
+    }
+
+    fn ratio(&self) -> Option<f32> {
+        match self {
+            AspectSource::Explicit(ratio) | AspectSource::Natural(ratio) => Some(*ratio),
+            AspectSource::Current => None,
+        }
+    }
+}
+
+impl Display for AspectSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AspectSource::Explicit(ratio) => write!(f, "explicit ({:.3})", ratio),
+            AspectSource::Natural(ratio) => write!(f, "natural ({:.3})", ratio),
+            AspectSource::Current => write!(f, "current"),
+        }
+    }
+}
 
+/// Compute the size the window should have in the given area
+fn compute_size(
+    target_node: &Node,
+    context: &Window,
+    state: &State,
+    area: &PlacementArea,
+    aspect: AspectSource,
+) -> Rect {
     let original_rect: Rect = target_node.rect.into();
     let mut rect: Rect = target_node.rect.into();
     // TODO: do this properly
     rect.height += target_node.deco_rect.height;
 
-    let ratio = if state.natural {
-        Some(aspect_ratio(
-            target_node.geometry.width,
-            target_node.geometry.height,
-        ))
-    } else {
-        None
-    };
     let scaled = &rect.scale(
-        state.width.clone().map(|w| w.into()),
-        state.height.clone().map(|h| h.into()),
+        state
+            .width
+            .as_ref()
+            .map(|w| w.resolve(&context.horizontal_density).into()),
+        state
+            .height
+            .as_ref()
+            .map(|h| h.resolve(&context.vertical_density).into()),
         &original_rect,
-        &proper_area,
-        ratio,
+        &area.padded,
+        aspect.ratio(),
     );
 
-    con.resize_node(
-        target_node.id,
-        AbsolutePixels::from(scaled.width as u32),
-        AbsolutePixels::from(scaled.height as u32),
-    )?;
-
     rect.height = scaled.height;
     rect.width = scaled.width;
 
-    let rect = proper_area.get_pos_for_rect_of_size(&state.position, &rect);
-    // we added a padding to our working area, but the center of the new area is not the same as the
-    // center of the old area, so we need to adjust the position of the window
-    let final_pos = rect.translate(state.padding as i32, state.padding as i32);
-
-    con.move_node_to_position(target_node.id, final_pos.x, final_pos.y)?;
-
-    Ok(state)
+    rect
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Rect {
     x: i32,
     y: i32,
@@ -201,6 +545,21 @@ impl Rect {
         rect
     }
 
+    /// The smallest rect that contains all the rects
+    fn bounds(rects: &[Rect]) -> Self {
+        let left = rects.iter().map(|r| r.x).min().unwrap_or(0);
+        let top = rects.iter().map(|r| r.y).min().unwrap_or(0);
+        let right = rects.iter().map(|r| r.x + r.width).max().unwrap_or(0);
+        let bottom = rects.iter().map(|r| r.y + r.height).max().unwrap_or(0);
+
+        Self {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        }
+    }
+
     fn translate(&self, x: i32, y: i32) -> Self {
         let mut rect = *self;
         rect.x += x;
@@ -208,6 +567,35 @@ impl Rect {
         rect
     }
 
+    /// Move the rect as little as possible to keep it inside an area of the given size at the
+    /// origin, sticking to the top left if it doesn't fit
+    fn keep_within(&self, width: i32, height: i32) -> Self {
+        let mut rect = *self;
+        rect.x = rect.x.min(width - rect.width).max(0);
+        rect.y = rect.y.min(height - rect.height).max(0);
+        rect
+    }
+
+    /// Move each edge to the closest spot that lines up with a whole device pixel
+    ///
+    /// `origin` is where the device pixel grid starts (the output), in the same coordinates as
+    /// this rect.
+    fn snap_to_device(&self, origin: &Rect, scale: f32) -> Self {
+        let snap = |value: i32, origin: i32| snap_to_device_pixel(value - origin, scale) + origin;
+
+        let left = snap(self.x, origin.x);
+        let top = snap(self.y, origin.y);
+        let right = snap(self.x + self.width, origin.x);
+        let bottom = snap(self.y + self.height, origin.y);
+
+        Rect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        }
+    }
+
     fn scale(
         &self,
         width: Option<Unit>,
@@ -261,17 +649,7 @@ impl Rect {
     }
 
     fn get_pos_for_rect_of_size(&self, pos: &Position, rect: &Rect) -> Rect {
-        let v_offset = match pos.0 {
-            Vertical::Top => 0.0,
-            Vertical::Middle => 0.5,
-            Vertical::Bottom => 1.0,
-        };
-
-        let h_offset = match pos.1 {
-            Horizontal::Left => 0.0,
-            Horizontal::Middle => 0.5,
-            Horizontal::Right => 1.0,
-        };
+        let (h_offset, v_offset) = position_offsets(pos);
 
         let x = (self.width as f32 * h_offset) - (rect.width as f32 * h_offset);
         let y = (self.height as f32 * v_offset) - (rect.height as f32 * v_offset);
@@ -284,6 +662,86 @@ impl Rect {
             height: rect.height,
         }
     }
+
+    /// Line up rects of the given sizes in a row or column, with a gap between each of them
+    ///
+    /// The strip is a column when placed in the middle of the left or right edge, and a row
+    /// otherwise. The strip as a whole is placed like a single window would be.
+    fn arrange_strip(&self, pos: &Position, sizes: &[Rect], gap: i32) -> Vec<Rect> {
+        let gaps = gap * (sizes.len() as i32 - 1).max(0);
+        let column = matches!(pos.0, Vertical::Middle) && !matches!(pos.1, Horizontal::Middle);
+
+        let bounds = if column {
+            Rect {
+                x: 0,
+                y: 0,
+                width: sizes.iter().map(|r| r.width).max().unwrap_or(0),
+                height: sizes.iter().map(|r| r.height).sum::<i32>() + gaps,
+            }
+        } else {
+            Rect {
+                x: 0,
+                y: 0,
+                width: sizes.iter().map(|r| r.width).sum::<i32>() + gaps,
+                height: sizes.iter().map(|r| r.height).max().unwrap_or(0),
+            }
+        };
+        let bounds = self.get_pos_for_rect_of_size(pos, &bounds);
+
+        // windows smaller than the strip are aligned to the same edge as the strip itself
+        let mut cursor = 0;
+        sizes
+            .iter()
+            .map(|size| {
+                let cell = if column {
+                    Rect {
+                        x: bounds.x,
+                        y: bounds.y + cursor,
+                        width: bounds.width,
+                        height: size.height,
+                    }
+                } else {
+                    Rect {
+                        x: bounds.x + cursor,
+                        y: bounds.y,
+                        width: size.width,
+                        height: bounds.height,
+                    }
+                };
+                cursor += if column { size.height } else { size.width } + gap;
+
+                cell.get_pos_for_rect_of_size(pos, size)
+                    .translate(cell.x, cell.y)
+            })
+            .collect()
+    }
+}
+
+/// How far along the width and height of an area a position is
+fn position_offsets(pos: &Position) -> (f32, f32) {
+    let h_offset = match pos.1 {
+        Horizontal::Left => 0.0,
+        Horizontal::Middle => 0.5,
+        Horizontal::Right => 1.0,
+    };
+
+    let v_offset = match pos.0 {
+        Vertical::Top => 0.0,
+        Vertical::Middle => 0.5,
+        Vertical::Bottom => 1.0,
+    };
+
+    (h_offset, v_offset)
+}
+
+impl Display for Rect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {})",
+            self.width, self.height, self.x, self.y
+        )
+    }
 }
 
 impl From<swayipc::Rect> for Rect {
@@ -318,12 +776,31 @@ fn scale_to_ratio(dimension: Dimension, ratio: f32) -> Dimension {
     }
 }
 
+/// How far to look for a logical coordinate that lands on a whole device pixel
+const MAX_SNAP_DISTANCE: i32 = 4;
+
+/// Find the closest logical coordinate that lands on a whole device pixel at the given scale
+fn snap_to_device_pixel(logical: i32, scale: f32) -> i32 {
+    let on_grid = |logical: &i32| {
+        let device = *logical as f32 * scale;
+        (device - device.round()).abs() < 0.01
+    };
+
+    (0..=MAX_SNAP_DISTANCE)
+        .flat_map(|distance| [logical - distance, logical + distance])
+        .find(on_grid)
+        .unwrap_or(logical)
+}
+
 fn unit_to_real_pixels(unit: Unit, target_px: i32, container_px: i32) -> i32 {
     let real = match unit {
         Unit::Absolute(AbsoluteUnit::Pixels(pixels)) => pixels.0 as f32,
         Unit::Absolute(AbsoluteUnit::Percentage(percentage)) => {
             container_px as f32 * (percentage.0 / 100.0)
         }
+        Unit::Absolute(AbsoluteUnit::Expression(expression)) => expression.evaluate(container_px),
+        Unit::Absolute(AbsoluteUnit::Physical(length)) => length.as_pixels(&Density::default()),
+        Unit::Absolute(AbsoluteUnit::DevicePixels(pixels)) => pixels.0 as f32,
         Unit::Relative(RelativeUnit::Pixels(pixels)) => target_px.saturating_add(pixels.0) as f32,
         Unit::Relative(RelativeUnit::Percentage(percentage)) => {
             let current = target_px as f32 / container_px as f32;
@@ -352,6 +829,128 @@ mod tests {
         assert_eq!(rect.height, 80);
     }
 
+    #[test]
+    fn test_placement_commands() {
+        let placement = Placement {
+            node_id: 42,
+            state: State::default(),
+            working: Rect::_new(0, 30, 1920, 1050),
+            padded: Rect::_new(12, 42, 1896, 1026),
+            aspect: AspectSource::Current,
+            rect: Rect::_new(1428, 768, 480, 270),
+        };
+
+        assert_eq!(
+            placement.command(&[]),
+            r#"[con_id="42"] resize set 480 px 270 px, move position 1428 768"#
+        );
+        assert_eq!(
+            placement.command(&["sticky enable".to_string()]),
+            r#"[con_id="42"] sticky enable, resize set 480 px 270 px, move position 1428 768"#
+        );
+        assert_eq!(placement.rect.to_string(), "480x270 at (1428, 768)");
+    }
+
+    #[test]
+    fn test_rect_keep_within() {
+        let rect = Rect::_new(-20, 50, 100, 100);
+        let rect = rect.keep_within(400, 120);
+        assert_eq!((rect.x, rect.y), (0, 20));
+
+        let rect = Rect::_new(350, 10, 100, 100).keep_within(400, 300);
+        assert_eq!((rect.x, rect.y), (300, 10));
+
+        // too big to fit at all
+        let rect = Rect::_new(30, 0, 500, 100).keep_within(400, 300);
+        assert_eq!(rect.x, 0);
+    }
+
+    #[test]
+    fn test_rect_bounds() {
+        let rects = [Rect::_new(10, 50, 100, 100), Rect::_new(120, 20, 50, 60)];
+        assert_eq!(Rect::bounds(&rects), Rect::_new(10, 20, 160, 130));
+    }
+
+    #[test]
+    fn test_slide_within() {
+        let area = Rect::_new(12, 42, 1000, 500);
+        let rect = Rect::_new(850, 0, 100, 100);
+        let mut state = State {
+            nudge_x: 30,
+            ..Default::default()
+        };
+        assert_eq!(slide_within(&mut state, &area, &rect), (30, 0));
+        assert_eq!(state.nudge_x, 30);
+
+        // only the nudges up to the edge are kept
+        state.nudge_x = 80;
+        assert_eq!(slide_within(&mut state, &area, &rect), (50, 0));
+        assert_eq!(state.nudge_x, 50);
+    }
+
+    #[test]
+    fn test_anchor_offset() {
+        let area = Rect::_new(12, 42, 1000, 500);
+        let mut state = State::default();
+        assert_eq!(anchor_offset(&state, &area), (0, 0));
+
+        state.offset_x = Some(RelativePercentage(30.0).into());
+        state.offset_y = Some(RelativePixels(-200).into());
+        state.nudge_x = 20;
+        assert_eq!(anchor_offset(&state, &area), (320, -200));
+    }
+
+    #[test]
+    fn test_snap_to_device_pixel() {
+        assert_eq!(snap_to_device_pixel(101, 1.0), 101);
+        assert_eq!(snap_to_device_pixel(101, 2.0), 101);
+        assert_eq!(snap_to_device_pixel(101, 1.5), 100);
+        assert_eq!(snap_to_device_pixel(103, 1.25), 104);
+        assert_eq!(snap_to_device_pixel(-3, 1.5), -4);
+    }
+
+    #[test]
+    fn test_rect_snap_to_device() {
+        let output = Rect::_new(0, 0, 1536, 864);
+        let rect = Rect::_new(12, 31, 501, 281).snap_to_device(&output, 1.25);
+
+        assert_eq!(rect.x, 12);
+        assert_eq!(rect.y, 32);
+        assert_eq!(rect.x + rect.width, 512);
+        assert_eq!(rect.y + rect.height, 312);
+
+        // the grid follows the output, even if the rect is relative to something else
+        let output = Rect::_new(-1, -1, 1536, 864);
+        let rect = Rect::_new(12, 31, 501, 281).snap_to_device(&output, 1.5);
+
+        assert_eq!(rect.x, 11);
+        assert_eq!(rect.y, 31);
+    }
+
+    #[test]
+    fn test_arrange_strip() {
+        let workspace = Rect::_new(0, 0, 1000, 500);
+        let sizes = [Rect::_new(0, 0, 200, 100), Rect::_new(0, 0, 300, 150)];
+
+        // a row along the bottom edge, with the windows aligned to the bottom
+        let pos = Position(Vertical::Bottom, Horizontal::Right);
+        let rects = workspace.arrange_strip(&pos, &sizes, 10);
+
+        assert_eq!((rects[0].x, rects[0].y), (490, 400));
+        assert_eq!((rects[0].width, rects[0].height), (200, 100));
+        assert_eq!((rects[1].x, rects[1].y), (700, 350));
+        assert_eq!((rects[1].width, rects[1].height), (300, 150));
+
+        // a column along the right edge, with the windows aligned to the right
+        let pos = Position(Vertical::Middle, Horizontal::Right);
+        let rects = workspace.arrange_strip(&pos, &sizes, 10);
+
+        assert_eq!((rects[0].x, rects[0].y), (800, 120));
+        assert_eq!((rects[1].x, rects[1].y), (700, 230));
+
+        assert!(workspace.arrange_strip(&pos, &[], 10).is_empty());
+    }
+
     #[test]
     fn test_get_pos_for_rect_of_size() {
         let workspace = Rect::_new(0, 0, 100, 100);
@@ -614,6 +1213,34 @@ mod tests {
         assert_eq!(rect.height, 55);
     }
 
+    #[test]
+    fn test_scale_with_explicit_ratio() {
+        let container = Rect::_new(0, 0, 1000, 1000);
+        let rect = Rect::_new(0, 0, 400, 400);
+        let rect = rect.scale(
+            Some(AbsolutePixels(320).into()),
+            None,
+            &rect,
+            &container,
+            Some(16.0 / 9.0),
+        );
+
+        assert_eq!(rect.width, 320);
+        assert_eq!(rect.height, 180);
+
+        let rect = Rect::_new(0, 0, 400, 400);
+        let rect = rect.scale(
+            None,
+            Some(AbsolutePercentage(30.0).into()),
+            &rect,
+            &container,
+            Some(4.0 / 3.0),
+        );
+
+        assert_eq!(rect.width, 400);
+        assert_eq!(rect.height, 300);
+    }
+
     #[test]
     fn test_aspect_ratio() {
         assert_eq!(aspect_ratio(1920, 1080), 16.0 / 9.0);
@@ -675,5 +1302,13 @@ mod tests {
             unit_to_real_pixels(RelativePercentage(50.0).into(), 250, 1000),
             750
         );
+        assert_eq!(
+            unit_to_real_pixels("calc(50% - 20px)".parse().unwrap(), 250, 1000),
+            480
+        );
+        assert_eq!(
+            unit_to_real_pixels("min(40%, 800px)".parse().unwrap(), 250, 3000),
+            800
+        );
     }
 }
@@ -0,0 +1,93 @@
+use std::{fs, path::Path};
+
+const DRM_PATH: &str = "/sys/class/drm";
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+/// The physical size of an output in millimeters, as reported by its EDID
+///
+/// Sway doesn't expose the physical size of outputs over IPC, so this reads the EDID of the
+/// matching DRM connector (ex: `card1-eDP-1` for the `eDP-1` output) directly from sysfs.
+pub fn physical_size(connector: &str) -> Option<(u32, u32)> {
+    physical_size_in(Path::new(DRM_PATH), connector)
+}
+
+fn physical_size_in(drm: &Path, connector: &str) -> Option<(u32, u32)> {
+    let suffix = format!("-{}", connector);
+
+    fs::read_dir(drm)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(&suffix))
+        // disconnected connectors of other GPUs can share the name, with an empty EDID
+        .find_map(|entry| {
+            fs::read(entry.path().join("edid"))
+                .ok()
+                .and_then(|edid| parse_edid_size(&edid))
+        })
+}
+
+fn parse_edid_size(edid: &[u8]) -> Option<(u32, u32)> {
+    if edid.len() < 128 || edid[..8] != EDID_HEADER {
+        return None;
+    }
+
+    // the first detailed timing descriptor has the size in millimeters
+    let width = edid[66] as u32 | ((edid[68] as u32 & 0xf0) << 4);
+    let height = edid[67] as u32 | ((edid[68] as u32 & 0x0f) << 8);
+    if width > 0 && height > 0 {
+        return Some((width, height));
+    }
+
+    // otherwise, fall back to the basic display parameters, which are only in centimeters
+    let (width, height) = (edid[21] as u32 * 10, edid[22] as u32 * 10);
+    if width > 0 && height > 0 {
+        return Some((width, height));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edid(dtd: (u8, u8, u8), basic: (u8, u8)) -> Vec<u8> {
+        let mut edid = vec![0; 128];
+        edid[..8].copy_from_slice(&EDID_HEADER);
+        (edid[21], edid[22]) = basic;
+        (edid[66], edid[67], edid[68]) = dtd;
+        edid
+    }
+
+    #[test]
+    fn test_parse_edid_size() {
+        // 597mm x 336mm, a 27" 16:9 monitor
+        assert_eq!(
+            parse_edid_size(&edid((0x55, 0x50, 0x21), (60, 34))),
+            Some((597, 336))
+        );
+        assert_eq!(
+            parse_edid_size(&edid((0, 0, 0), (60, 34))),
+            Some((600, 340))
+        );
+        assert_eq!(parse_edid_size(&edid((0, 0, 0), (0, 0))), None);
+        assert_eq!(parse_edid_size(&[0; 128]), None);
+        assert_eq!(parse_edid_size(&EDID_HEADER), None);
+    }
+
+    #[test]
+    fn test_physical_size_skips_disconnected_connectors() {
+        let drm = std::env::temp_dir().join(format!("sway-gravity-drm-{}", std::process::id()));
+        for (card, edid) in [
+            ("card0-DP-1", vec![]),
+            ("card1-DP-1", edid((0x55, 0x50, 0x21), (60, 34))),
+        ] {
+            fs::create_dir_all(drm.join(card)).unwrap();
+            fs::write(drm.join(card).join("edid"), edid).unwrap();
+        }
+
+        let size = physical_size_in(&drm, "DP-1");
+        fs::remove_dir_all(&drm).unwrap();
+        assert_eq!(size, Some((597, 336)));
+    }
+}
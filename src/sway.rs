@@ -1,8 +1,74 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    error::Error,
+    fmt::Display,
+    ops::{Deref, DerefMut},
+};
 
 use swayipc::{Connection, Fallible, Node};
 
-use crate::daemon::unit::{AbsolutePercentage, AbsoluteUnit};
+use crate::{daemon::unit::Density, output::physical_size};
+
+/// The special output that holds the scratchpad workspace
+pub const SCRATCHPAD_OUTPUT: &str = "__i3";
+
+/// The action that adds the mark to a node
+pub fn mark_action(mark: &str) -> String {
+    format!("mark --add {}", quote(mark))
+}
+
+/// Quote an argument of a sway command, so it can't end the command or start another one
+fn quote(argument: &str) -> String {
+    format!(
+        "\"{}\"",
+        argument.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// A command list scoped to one node, which sway applies without redrawing in between
+pub fn node_command(node_id: i64, actions: &[String]) -> String {
+    format!(r#"[con_id="{}"] {}"#, node_id, actions.join(", "))
+}
+
+/// Why a command list wasn't fully applied
+#[derive(Debug)]
+pub enum CommandError {
+    SwayIPC(swayipc::Error),
+    /// Sway rejected some of the commands, while the others stay applied
+    Rejected {
+        total: usize,
+        failures: Vec<String>,
+    },
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::SwayIPC(err) => write!(f, "SwayIPC error: {}", err),
+            CommandError::Rejected { total, failures } => write!(
+                f,
+                "{} of {} sway commands failed: {}",
+                failures.len(),
+                total,
+                failures.join("; ")
+            ),
+        }
+    }
+}
+
+impl Error for CommandError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CommandError::SwayIPC(err) => Some(err),
+            CommandError::Rejected { .. } => None,
+        }
+    }
+}
+
+impl From<swayipc::Error> for CommandError {
+    fn from(err: swayipc::Error) -> Self {
+        Self::SwayIPC(err)
+    }
+}
 
 pub struct SwayConnection(Connection);
 
@@ -26,33 +92,86 @@ impl SwayConnection {
     }
 
     pub fn find_working_area_for(&mut self, node_id: i64) -> Fallible<Option<swayipc::Rect>> {
-        Ok(self
-            .get_workspaces()?
+        let workspaces = self.get_workspaces()?;
+
+        // nodes in the scratchpad aren't on any workspace, but will be shown on the focused one
+        Ok(workspaces
             .iter()
             .find(|w| w.focus.contains(&node_id))
+            .or_else(|| workspaces.iter().find(|w| w.focused))
             .map(|w| w.rect))
     }
 
-    pub fn move_node_to_position(&mut self, node_id: i64, x: i32, y: i32) -> Fallible<()> {
-        let cmd = format!(r#"[con_id="{}"] move position {} {}"#, node_id, x, y);
-        self.run_command(cmd)?;
+    pub fn move_node_to_scratchpad(&mut self, node_id: i64) -> Result<(), CommandError> {
+        self.run_commands(&node_command(node_id, &["move scratchpad".to_string()]))
+    }
+
+    /// Run a command list, failing if any of its commands failed
+    ///
+    /// Sway reports an outcome for every command in the list, and the ones that succeeded stay
+    /// applied, so a failure can leave the window partially updated.
+    pub fn run_commands(&mut self, cmd: &str) -> Result<(), CommandError> {
+        let outcomes = self.run_command(cmd)?;
+        let total = outcomes.len();
+        let failures: Vec<String> = outcomes
+            .into_iter()
+            .filter_map(|outcome| outcome.err())
+            .map(|err| err.to_string())
+            .collect();
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(CommandError::Rejected { total, failures })
+        }
+    }
+
+    pub fn mark_node(&mut self, node_id: i64, mark: &str) -> Result<(), CommandError> {
+        self.run_commands(&node_command(node_id, &[mark_action(mark)]))
+    }
+
+    pub fn unmark(&mut self, mark: &str) -> Result<(), CommandError> {
+        self.run_commands(&format!("unmark {}", quote(mark)))
+    }
+
+    pub fn find_node_with_mark(&mut self, mark: &str) -> Fallible<Option<swayipc::Node>> {
+        Ok(self
+            .get_tree()?
+            .find(|node| node.marks.iter().any(|m| m == mark)))
+    }
+
+    pub fn has_mark(&mut self, mark: &str) -> Fallible<bool> {
+        Ok(self.get_marks()?.iter().any(|m| m == mark))
+    }
+
+    pub fn is_node_in_scratchpad(&mut self, node_id: i64) -> Fallible<bool> {
+        Ok(self.find_output_for(node_id)?.as_deref() == Some(SCRATCHPAD_OUTPUT))
+    }
 
-        Ok(())
+    pub fn find_node(&mut self, node_id: i64) -> Fallible<Option<swayipc::Node>> {
+        Ok(self.get_tree()?.find(|node| node.id == node_id))
     }
 
-    pub fn resize_node<W: Into<AbsoluteUnit>, H: Into<AbsoluteUnit>>(
-        &mut self,
-        node_id: i64,
-        width: W,
-        height: H,
-    ) -> Fallible<()> {
-        let width: AbsoluteUnit = width.into();
-        let height: AbsoluteUnit = height.into();
+    pub fn find_output_for(&mut self, node_id: i64) -> Fallible<Option<String>> {
+        let tree = self.get_tree()?;
+
+        Ok(tree
+            .nodes
+            .iter()
+            .find(|output| output.iter().any(|node| node.id == node_id))
+            .and_then(|output| output.name.clone()))
+    }
 
-        let cmd = format!(r#"[con_id="{}"] resize set {} {}"#, node_id, width, height);
-        self.run_command(cmd)?;
+    pub fn find_output_metrics_for(&mut self, node_id: i64) -> Fallible<Option<OutputMetrics>> {
+        let name = self.find_output_for(node_id)?;
+        let outputs = self.get_outputs()?;
 
-        Ok(())
+        // like the working area, nodes in the scratchpad will be shown on the focused output
+        Ok(outputs
+            .iter()
+            .find(|output| Some(&output.name) == name.as_ref())
+            .or_else(|| outputs.iter().find(|output| output.focused))
+            .map(OutputMetrics::from))
     }
 
     pub fn _get_parent_node(&mut self, node_id: i64) -> Fallible<Option<swayipc::Node>> {
@@ -65,6 +184,55 @@ impl SwayConnection {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct OutputMetrics {
+    pub area: swayipc::Rect,
+    pub horizontal_density: Density,
+    pub vertical_density: Density,
+}
+
+impl From<&swayipc::Output> for OutputMetrics {
+    fn from(output: &swayipc::Output) -> Self {
+        // disabled outputs report a scale of -1
+        let scale = output.scale.filter(|scale| *scale > 0.0).unwrap_or(1.0) as f32;
+        let unknown = Density {
+            scale,
+            ..Default::default()
+        };
+
+        let (horizontal_density, vertical_density) =
+            match (output.current_mode, physical_size(&output.name)) {
+                (Some(mode), Some((width_mm, height_mm))) => {
+                    // the mode and the physical size are both in the panel's native orientation
+                    let horizontal = Density {
+                        px_per_mm: mode.width as f32 / scale / width_mm as f32,
+                        scale,
+                    };
+                    let vertical = Density {
+                        px_per_mm: mode.height as f32 / scale / height_mm as f32,
+                        scale,
+                    };
+
+                    match output.transform.as_deref() {
+                        Some(transform)
+                            if transform.ends_with("90") || transform.ends_with("270") =>
+                        {
+                            (vertical, horizontal)
+                        }
+                        _ => (horizontal, vertical),
+                    }
+                }
+                _ => (unknown, unknown),
+            };
+
+        Self {
+            area: output.rect,
+            horizontal_density,
+            vertical_density,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Dimension {
     Width(i32),
@@ -87,24 +255,14 @@ impl WindowDimension {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Coordinate {
-    x: i32,
-    y: i32,
-}
-
-impl Coordinate {
-    pub fn new(x: i32, y: i32) -> Self {
-        Self { x, y }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct Window {
-    pub position: Coordinate,
     pub dimensions: WindowDimension,
     pub natural_dimensions: Option<WindowDimension>,
     pub working_area: swayipc::Rect,
+    pub output_area: swayipc::Rect,
+    pub horizontal_density: Density,
+    pub vertical_density: Density,
 }
 
 impl Window {
@@ -112,9 +270,15 @@ impl Window {
         let working_area = con
             .find_working_area_for(node.id)?
             .expect("Node should have a working area");
+        let output = con
+            .find_output_metrics_for(node.id)?
+            .unwrap_or(OutputMetrics {
+                area: working_area,
+                horizontal_density: Density::default(),
+                vertical_density: Density::default(),
+            });
 
         Ok(Self {
-            position: Coordinate::new(node.rect.x, node.rect.y),
             dimensions: WindowDimension {
                 width: node.rect.width,
                 height: node.rect.height,
@@ -124,18 +288,23 @@ impl Window {
                 height: node.geometry.height,
             }),
             working_area,
+            output_area: output.area,
+            horizontal_density: output.horizontal_density,
+            vertical_density: output.vertical_density,
         })
     }
+}
 
-    pub fn width_in_parent_percentage(&self) -> AbsolutePercentage {
-        AbsolutePercentage::from(
-            self.dimensions.width as f32 / self.working_area.width as f32 * 100.0,
-        )
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn height_in_parent_percentage(&self) -> AbsolutePercentage {
-        AbsolutePercentage::from(
-            self.dimensions.height as f32 / self.working_area.height as f32 * 100.0,
-        )
+    #[test]
+    fn test_mark_action() {
+        assert_eq!(mark_action("_gravity_pip"), r#"mark --add "_gravity_pip""#);
+        assert_eq!(
+            mark_action(r#"x", exec foo"#),
+            r#"mark --add "x\", exec foo""#
+        );
     }
 }